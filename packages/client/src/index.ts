@@ -7,6 +7,8 @@ import {
   SystemProgram,
   Connection,
   ComputeBudgetProgram,
+  AccountMeta,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
 } from "@solana/web3.js";
 import { ImpactNft, IDL } from "./types/impact_nft.js";
 import {
@@ -24,23 +26,59 @@ export const PROGRAM_ID = new PublicKey(
   "SUNFT6ErsQvMcDzMcGyndq2P31wYCFs6G6WEcoyGkGc"
 );
 
+// passed as the max fee payable to accept any fee
+const NO_MAX_FEE_PAYABLE = new BN("18446744073709551615"); // u64::MAX
+
 export interface Level {
   offset: anchor.BN;
+  minPrincipal: anchor.BN; // required in addition to the offset
+  minDurationAtPreviousLevel: anchor.BN; // seconds at the previous level before promotion
   uri: string;
   name: string;
   symbol: string;
   collectionMint: PublicKey;
+  fee?: FeeConfig | null; // overrides the global fee for this level
+  royaltyBasisPoints?: number | null;
+  creators: LevelCreator[]; // if empty, the token authority is the sole creator
   index: number;
 }
 
+export type LevelCreator = {
+  address: PublicKey;
+  share: number; // percentage, shares must sum to 100
+};
+
 export type RawLevel = Omit<Level, "index">;
 
+// add the index to each level
+const withIndex = (levels: RawLevel[]): Level[] =>
+  levels.map((level, index) => ({ ...level, index }));
+
 export type FeeConfig = {
   fee: BN; // fixed or basis point (0- 10_000)
-  recipient: PublicKey;
   feeType: { percentage: {} } | { fixed: {} };
   coinType: { native: {} } | { spl: {} };
   splTokenMint: PublicKey | null;
+  minFee: BN; // lower bound for percentage fees
+  maxFee: BN | null; // upper bound for percentage fees
+};
+
+// How the update fee scales when an NFT moves up several levels at once
+export type LevelUpFeeScaling = { flat: {} } | { perLevel: {} };
+export type DowngradePolicy =
+  | { allow: {} }
+  | { forbid: {} }
+  | { keepHighest: {} };
+
+export type FeeRecipient = {
+  address: PublicKey; // token fees are paid to its associated token account
+  share: number; // basis points of the fees (0 - 10_000)
+};
+
+// How fees collected in the fee vaults are split when withdrawn
+export type FeeDistribution = {
+  recipients: FeeRecipient[]; // shares must sum to 10_000
+  primaryRecipient: number; // receives any rounding dust
 };
 
 type BaseMintAccounts = {
@@ -53,12 +91,17 @@ type BaseMintAccounts = {
   masterEdition: PublicKey;
   offsetMetadata: PublicKey;
   offsetTiers: PublicKey;
+  levelCounts: PublicKey;
 };
 
 type FeeAccounts = {
+  feeVault: PublicKey | null;
+  feeVaultTokenAccount: PublicKey | null;
   payerTokenAccount: PublicKey | null;
-  recipient: PublicKey | null;
-  recipientTokenAccount: PublicKey | null;
+  feeMint: PublicKey | null;
+  feeTokenProgram: PublicKey | null;
+  feeWaiver: PublicKey | null;
+  instructionsSysvar: PublicKey | null;
 };
 
 type CollectionAccounts = {
@@ -83,18 +126,47 @@ export const setUpAnchor = (): anchor.AnchorProvider => {
   return provider;
 };
 
-const getOffsetTiersAddress = (stateAddress: PublicKey) =>
+// version 0 has no version seed, it keeps the address of the unversioned offset tiers
+const getOffsetTiersAddress = (stateAddress: PublicKey, version: number) =>
   PublicKey.findProgramAddressSync(
-    [Buffer.from("offset_tiers"), stateAddress.toBuffer()],
+    [
+      Buffer.from("offset_tiers"),
+      stateAddress.toBuffer(),
+      version === 0
+        ? Buffer.alloc(0)
+        : new BN(version).toArrayLike(Buffer, "le", 2),
+    ],
     PROGRAM_ID
   )[0];
 
+const getLevelCountsAddress = (offsetTiers: PublicKey) =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("level_counts"), offsetTiers.toBuffer()],
+    PROGRAM_ID
+  )[0];
+
+export type GlobalStateUpdate = {
+  adminUpdateAuthority: PublicKey;
+  adminMintAuthority: PublicKey;
+  levels: number;
+  fee: FeeConfig | null;
+  feeDistribution: FeeDistribution | null;
+  updateFee: FeeConfig | null;
+  updateFeeScaling: LevelUpFeeScaling;
+  feeActivationSlot: BN | null; // fee changes apply from this slot if it is in the future
+  downgradePolicy: DowngradePolicy;
+};
+
 export interface ImpactNftClientConfig {
   mintAuthority: PublicKey;
   updateAuthority: PublicKey;
   tokenAuthority: PublicKey;
   levels: number;
   fee?: FeeConfig;
+  feeDistribution?: FeeDistribution;
+  updateFee?: FeeConfig;
+  updateFeeScaling: LevelUpFeeScaling;
+  downgradePolicy: DowngradePolicy;
 }
 
 export class ImpactNftClient {
@@ -124,7 +196,11 @@ export class ImpactNftClient {
   public static async register(
     adminMintAuthority: PublicKey,
     levels: number,
-    fee?: FeeConfig
+    fee?: FeeConfig,
+    feeDistribution?: FeeDistribution,
+    updateFee?: FeeConfig,
+    updateFeeScaling: LevelUpFeeScaling = { flat: {} },
+    downgradePolicy: DowngradePolicy = { allow: {} }
   ): Promise<ImpactNftClient> {
     const client = new ImpactNftClient(setUpAnchor());
     const stateKey = Keypair.generate();
@@ -143,6 +219,10 @@ export class ImpactNftClient {
         adminMintAuthority,
         levels,
         fee: fee || null,
+        feeDistribution: feeDistribution || null,
+        updateFee: updateFee || null,
+        updateFeeScaling,
+        downgradePolicy,
       })
       .accounts(accounts)
       .signers([stateKey])
@@ -158,6 +238,61 @@ export class ImpactNftClient {
     return client;
   }
 
+  /**
+   * Upgrades a global state and its offset tiers created by the previously deployed
+   * program to the current account layouts. The legacy fee recipient is replaced by
+   * the fee distribution. The offset metadata of each existing nft must then be
+   * upgraded with upgradeOffsetMetadata, the levels cannot be edited until they all are.
+   * The legacy offset metadata does not record its global state, so by default every
+   * legacy nft of the program is counted, pass legacyNfts if it has several global states
+   */
+  public static async upgrade(
+    provider: AnchorProvider,
+    stateAddress: PublicKey,
+    feeDistribution?: FeeDistribution,
+    legacyNfts?: number
+  ): Promise<ImpactNftClient> {
+    const client = new ImpactNftClient(provider);
+    const offsetTiers = getOffsetTiersAddress(stateAddress, 0);
+    const legacyOffsetMetadataSize = 19;
+    legacyNfts ??= await provider.connection
+      .getProgramAccounts(PROGRAM_ID, {
+        dataSlice: { offset: 0, length: 0 },
+        filters: [
+          { dataSize: legacyOffsetMetadataSize },
+          { memcmp: client.program.coder.accounts.memcmp("offsetMetadata") },
+        ],
+      })
+      .then((accounts) => accounts.length);
+
+    await client.program.methods
+      .upgradeGlobalState(feeDistribution || null)
+      .accounts({
+        adminUpdateAuthority: provider.publicKey,
+        payer: provider.publicKey,
+        globalState: stateAddress,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+      .then(() => confirm(provider.connection));
+
+    await client.program.methods
+      .upgradeOffsetTiers(new BN(legacyNfts))
+      .accounts({
+        adminUpdateAuthority: provider.publicKey,
+        payer: provider.publicKey,
+        globalState: stateAddress,
+        offsetTiers,
+        levelCounts: getLevelCountsAddress(offsetTiers),
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+      .then(() => confirm(provider.connection));
+
+    await client.init(stateAddress);
+    return client;
+  }
+
   public static async get(
     provider: AnchorProvider,
     stateAddress: PublicKey
@@ -184,9 +319,55 @@ export class ImpactNftClient {
       tokenAuthority,
       levels: this.state.levels as number,
       fee: this.state.fee as FeeConfig,
+      feeDistribution: this.state.feeDistribution as FeeDistribution,
+      updateFee: this.state.updateFee as FeeConfig,
+      updateFeeScaling: this.state.updateFeeScaling as LevelUpFeeScaling,
+      downgradePolicy: this.state.downgradePolicy as DowngradePolicy,
     };
   }
 
+  /**
+   * Updates the global state, keeping any field that is not given.
+   * Removing the fee distribution passes every version of the offset tiers,
+   * so the program can check that no level still charges a fee
+   */
+  public async updateGlobalState(update: Partial<GlobalStateUpdate>) {
+    if (!this.stateAddress || !this.state) throw new Error("Client not initialized");
+
+    const input: GlobalStateUpdate = {
+      adminUpdateAuthority: this.state.adminUpdateAuthority,
+      adminMintAuthority: this.state.adminMintAuthority,
+      levels: this.state.levels as number,
+      fee: this.state.fee as FeeConfig | null,
+      feeDistribution: this.state.feeDistribution as FeeDistribution | null,
+      updateFee: this.state.updateFee as FeeConfig | null,
+      updateFeeScaling: this.state.updateFeeScaling as LevelUpFeeScaling,
+      feeActivationSlot: null,
+      downgradePolicy: this.state.downgradePolicy as DowngradePolicy,
+      ...update,
+    };
+    const tiersVersion = this.state.tiersVersion as number;
+    const offsetTiersAccounts: AccountMeta[] = input.feeDistribution
+      ? []
+      : [...Array(tiersVersion + 1).keys()].map((version) => ({
+          pubkey: this.getOffsetTiersAddress(version),
+          isSigner: false,
+          isWritable: false,
+        }));
+
+    await this.program.methods
+      .updateGlobalState(input)
+      .accounts({
+        adminUpdateAuthority: this.provider.publicKey,
+        globalState: this.stateAddress,
+      })
+      .remainingAccounts(offsetTiersAccounts)
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+
+    await this.init(this.stateAddress);
+  }
+
   public details() {
     if (!this.state) throw new Error("not initialized");
 
@@ -203,9 +384,40 @@ export class ImpactNftClient {
     )[0];
   }
 
-  public getOffsetTiersAddress(): PublicKey {
+  /** Defaults to the latest version of the offset tiers */
+  public getOffsetTiersAddress(
+    version: number = this.state?.tiersVersion as number
+  ): PublicKey {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+    return getOffsetTiersAddress(this.stateAddress, version);
+  }
+
+  /** The nft counts of a version of the offset tiers, defaults to the latest version */
+  public getLevelCountsAddress(
+    offsetTiers: PublicKey = this.getOffsetTiersAddress()
+  ): PublicKey {
+    return getLevelCountsAddress(offsetTiers);
+  }
+
+  /** The SOL vault uses the default pubkey in place of a mint */
+  public getFeeVaultAddress(mint: PublicKey | null): PublicKey {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+    return PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("fee_vault"),
+        this.stateAddress.toBuffer(),
+        (mint ?? PublicKey.default).toBuffer(),
+      ],
+      PROGRAM_ID
+    )[0];
+  }
+
+  public getFeeWaiverAddress(key: PublicKey): PublicKey {
     if (!this.stateAddress) throw new Error("Client not initialized");
-    return getOffsetTiersAddress(this.stateAddress);
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("fee_waiver"), this.stateAddress.toBuffer(), key.toBuffer()],
+      PROGRAM_ID
+    )[0];
   }
 
   public getOffsetMetadataAddress(mint: PublicKey): PublicKey {
@@ -245,6 +457,39 @@ export class ImpactNftClient {
     )[0];
   }
 
+  public getCollectionAuthorityRecordAddress(mint: PublicKey): PublicKey {
+    if (!this.config) throw new Error("Client not initialized");
+    return PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        mint.toBuffer(),
+        Buffer.from("collection_authority"),
+        this.config.tokenAuthority.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+  }
+
+  /** the accounts the program checks for each collection not yet used by the registered levels */
+  public getCollectionVerificationAccounts(levels: RawLevel[]): AccountMeta[] {
+    const known = (this.tiers?.levels ?? []) as RawLevel[];
+    return levels
+      .filter(
+        (level) =>
+          !known.some((k) =>
+            (k.collectionMint as PublicKey).equals(level.collectionMint)
+          )
+      )
+      .flatMap((level) =>
+        [
+          this.getMetadataAddress(level.collectionMint),
+          this.getMasterEditionAddress(level.collectionMint),
+          this.getCollectionAuthorityRecordAddress(level.collectionMint),
+        ].map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }))
+      );
+  }
+
   public async createCollectionMint(
     uri: string,
     name: string
@@ -281,9 +526,12 @@ export class ImpactNftClient {
       .accounts({
         adminUpdateAuthority: this.provider.publicKey,
         globalState: this.stateAddress,
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
         offsetTiers: offsetTiersAddress,
+        levelCounts: this.getLevelCountsAddress(offsetTiersAddress),
         systemProgram: SystemProgram.programId,
       })
+      .remainingAccounts(this.getCollectionVerificationAccounts(levels))
       .rpc()
       .then(() => confirm(this.provider.connection));
 
@@ -300,9 +548,14 @@ export class ImpactNftClient {
       .addLevels(levels)
       .accounts({
         adminUpdateAuthority: this.provider.publicKey,
+        payer: this.provider.publicKey,
         globalState: this.stateAddress,
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
         offsetTiers: offsetTiersAddress,
+        levelCounts: this.getLevelCountsAddress(offsetTiersAddress),
+        systemProgram: SystemProgram.programId,
       })
+      .remainingAccounts(this.getCollectionVerificationAccounts(levels))
       .rpc()
       .then(() => confirm(this.provider.connection));
 
@@ -311,9 +564,66 @@ export class ImpactNftClient {
     );
   }
 
+  public async insertLevel(index: number, level: RawLevel) {
+    return this.editLevel(
+      this.program.methods
+        .insertLevel(index, level)
+        .accounts({
+          adminUpdateAuthority: this.provider.publicKey,
+          payer: this.provider.publicKey,
+          globalState: this.stateAddress,
+          tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+          offsetTiers: this.getOffsetTiersAddress(),
+          levelCounts: this.getLevelCountsAddress(),
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(this.getCollectionVerificationAccounts([level]))
+    );
+  }
+
+  public async removeLevel(index: number) {
+    return this.editLevel(
+      this.program.methods.removeLevel(index).accounts({
+        adminUpdateAuthority: this.provider.publicKey,
+        payer: this.provider.publicKey,
+        globalState: this.stateAddress,
+        offsetTiers: this.getOffsetTiersAddress(),
+        levelCounts: this.getLevelCountsAddress(),
+        systemProgram: SystemProgram.programId,
+      })
+    );
+  }
+
+  public async updateLevel(index: number, level: RawLevel) {
+    return this.editLevel(
+      this.program.methods
+        .updateLevel(index, level)
+        .accounts({
+          adminUpdateAuthority: this.provider.publicKey,
+          globalState: this.stateAddress,
+          tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+          offsetTiers: this.getOffsetTiersAddress(),
+          levelCounts: this.getLevelCountsAddress(),
+        })
+        .remainingAccounts(this.getCollectionVerificationAccounts([level]))
+    );
+  }
+
+  private async editLevel(builder: { rpc: () => Promise<string> }) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    await builder.rpc().then(() => confirm(this.provider.connection));
+
+    this.tiers = await this.program.account.offsetTiers.fetchNullable(
+      this.getOffsetTiersAddress()
+    );
+  }
+
+  /** Defaults to the accounts for minting into the first level */
   public async getMintNftAccounts(
     mint: PublicKey,
-    user: PublicKey
+    user: PublicKey,
+    level: Level = this.levels[0]
   ): Promise<AllMintAccounts> {
     if (!this.stateAddress || !this.config)
       throw new Error("Client not initialized");
@@ -324,8 +634,10 @@ export class ImpactNftClient {
     const offsetTiers = this.getOffsetTiersAddress();
     const userTokenAccount = getAssociatedTokenAddressSync(mint, user, true);
 
-    const collectionAccounts = await this.getCollectionAccounts();
-    const feeAccounts = this.getFeeAccounts(user);
+    const collectionAccounts = await this.getCollectionAccounts(level);
+    const feeAccounts = await this.getFeeAccounts(
+      level?.fee ?? this.config.fee
+    );
 
     return {
       program: PROGRAM_ID,
@@ -337,46 +649,217 @@ export class ImpactNftClient {
       masterEdition,
       offsetMetadata,
       offsetTiers,
+      levelCounts: this.getLevelCountsAddress(offsetTiers),
       ...collectionAccounts,
       ...feeAccounts,
     };
   }
-  private getFeeAccounts(user: PublicKey): FeeAccounts {
+
+  /**
+   * Fees are paid by the provider wallet, which pays for the transaction.
+   * Its fee waiver is passed if one exists
+   */
+  private async getFeeAccounts(
+    fee: FeeConfig | undefined
+  ): Promise<FeeAccounts> {
     if (!this.config) throw new Error("Client not initialized");
     const feeAccounts: FeeAccounts = {
+      feeVault: null,
+      feeVaultTokenAccount: null,
       payerTokenAccount: null,
-      recipient: null,
-      recipientTokenAccount: null,
+      feeMint: null,
+      feeTokenProgram: null,
+      feeWaiver: null,
+      instructionsSysvar: null,
     };
-    if (this.config.fee) {
-      if (this.config.fee.coinType.hasOwnProperty("spl")) {
-        if (!this.config.fee.splTokenMint)
-          throw new Error("No spl token mint provided for fee of type SPL");
-        feeAccounts.payerTokenAccount = getAssociatedTokenAddressSync(
-          this.config.fee.splTokenMint,
-          user,
-          true
-        );
-
-        feeAccounts.recipientTokenAccount = getAssociatedTokenAddressSync(
-          this.config.fee.splTokenMint,
-          this.config.fee.recipient,
-          true
-        );
-      } else {
-        feeAccounts.recipient = this.config.fee.recipient;
-      }
+    if (!fee) return feeAccounts;
+
+    feeAccounts.feeVault = this.getFeeVaultAddress(fee.splTokenMint);
+    const feeWaiver = this.getFeeWaiverAddress(this.provider.publicKey);
+    if (await this.provider.connection.getAccountInfo(feeWaiver)) {
+      feeAccounts.feeWaiver = feeWaiver;
+      // only read for waivers keyed by a calling program
+      feeAccounts.instructionsSysvar = SYSVAR_INSTRUCTIONS_PUBKEY;
+    }
+    if (fee.coinType.hasOwnProperty("spl")) {
+      if (!fee.splTokenMint)
+        throw new Error("No spl token mint provided for fee of type SPL");
+      // the fee mint may be owned by either SPL Token or Token-2022
+      const mintAccount = await this.provider.connection.getAccountInfo(
+        fee.splTokenMint
+      );
+      if (!mintAccount) throw new Error("Fee mint not found");
+
+      feeAccounts.feeMint = fee.splTokenMint;
+      feeAccounts.feeTokenProgram = mintAccount.owner;
+      feeAccounts.payerTokenAccount = getAssociatedTokenAddressSync(
+        fee.splTokenMint,
+        this.provider.publicKey,
+        true,
+        mintAccount.owner
+      );
+      feeAccounts.feeVaultTokenAccount = getAssociatedTokenAddressSync(
+        fee.splTokenMint,
+        feeAccounts.feeVault,
+        true,
+        mintAccount.owner
+      );
     }
 
     return feeAccounts;
   }
 
-  public getLevelForOffset(offset: anchor.BN): Level | null {
+  public async createFeeVault(
+    feeMint: PublicKey | null,
+    feeTokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    const feeVault = this.getFeeVaultAddress(feeMint);
+    await this.program.methods
+      .createFeeVault()
+      .accounts({
+        payer: this.provider.publicKey,
+        adminUpdateAuthority: this.provider.publicKey,
+        globalState: this.stateAddress,
+        feeMint,
+        feeVault,
+        feeVaultTokenAccount: feeMint
+          ? getAssociatedTokenAddressSync(
+              feeMint,
+              feeVault,
+              true,
+              feeTokenProgram
+            )
+          : null,
+        feeTokenProgram: feeMint ? feeTokenProgram : null,
+        associatedTokenProgram: feeMint ? ASSOCIATED_TOKEN_PROGRAM_ID : null,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  /**
+   * Waives or discounts fees for a wallet or calling program
+   * discount is in basis points, 10_000 waives the fee entirely
+   */
+  public async addFeeWaiver(key: PublicKey, discount: number) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    await this.program.methods
+      .addFeeWaiver({ key, discount })
+      .accounts({
+        payer: this.provider.publicKey,
+        adminUpdateAuthority: this.provider.publicKey,
+        globalState: this.stateAddress,
+        feeWaiver: this.getFeeWaiverAddress(key),
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  public async removeFeeWaiver(key: PublicKey) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    await this.program.methods
+      .removeFeeWaiver()
+      .accounts({
+        adminUpdateAuthority: this.provider.publicKey,
+        globalState: this.stateAddress,
+        feeWaiver: this.getFeeWaiverAddress(key),
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  /** Upgrades the offset metadata of an nft minted by the previously deployed program */
+  public async upgradeOffsetMetadata(mint: PublicKey) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+    const offsetTiers = this.getOffsetTiersAddress(0);
+
+    await this.program.methods
+      .upgradeOffsetMetadata()
+      .accounts({
+        payer: this.provider.publicKey,
+        globalState: this.stateAddress,
+        offsetTiers,
+        levelCounts: this.getLevelCountsAddress(offsetTiers),
+        offsetMetadata: this.getOffsetMetadataAddress(mint),
+        mint,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  // Sweeps a fee vault to the recipients of the fee distribution
+  public async withdrawFees(
+    feeMint: PublicKey | null,
+    feeTokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ) {
+    if (!this.stateAddress || !this.config)
+      throw new Error("Client not initialized");
+    if (!this.config.feeDistribution)
+      throw new Error("No fee distribution configured");
+
+    const feeVault = this.getFeeVaultAddress(feeMint);
+    // recipients are passed as remaining accounts, in fee distribution order
+    const recipients: AccountMeta[] =
+      this.config.feeDistribution.recipients.map((recipient) => ({
+        pubkey: feeMint
+          ? getAssociatedTokenAddressSync(
+              feeMint,
+              recipient.address,
+              true,
+              feeTokenProgram
+            )
+          : recipient.address,
+        isSigner: false,
+        isWritable: true,
+      }));
+
+    await this.program.methods
+      .withdrawFees()
+      .accounts({
+        adminUpdateAuthority: this.provider.publicKey,
+        globalState: this.stateAddress,
+        feeVault,
+        feeVaultTokenAccount: feeMint
+          ? getAssociatedTokenAddressSync(
+              feeMint,
+              feeVault,
+              true,
+              feeTokenProgram
+            )
+          : null,
+        feeMint,
+        feeTokenProgram: feeMint ? feeTokenProgram : null,
+      })
+      .remainingAccounts(recipients)
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  /**
+   * If a principal is given, the level's principal threshold must be met as well.
+   * Levels default to the latest offset tiers version
+   */
+  public getLevelForOffset(
+    offset: anchor.BN,
+    principal?: anchor.BN,
+    levels: Level[] = this.levels
+  ): Level | null {
     if (!this.tiers) throw new Error("Client not initialized");
     // search backwards so we get the highest level
-    const level = this.levels
+    const level = [...levels]
       .reverse()
-      .find((level, index) => offset.gte(level.offset));
+      .find(
+        (level) =>
+          offset.gte(level.offset) &&
+          (principal === undefined || principal.gte(level.minPrincipal))
+      );
     return level || null;
   }
 
@@ -399,7 +882,8 @@ export class ImpactNftClient {
 
   public async getUpdateNftAccounts(
     mint: PublicKey,
-    offset: anchor.BN
+    offset: anchor.BN,
+    principal: anchor.BN
   ): Promise<{
     collectionMint: PublicKey;
     collectionMetadata: PublicKey;
@@ -408,18 +892,29 @@ export class ImpactNftClient {
     newCollectionMetadata: PublicKey;
     newCollectionMasterEdition: PublicKey;
     tokenMetadataProgram: PublicKey;
+    offsetTiers: PublicKey;
+    levelCounts: PublicKey;
   }> {
     if (!this.stateAddress || !this.config)
       throw new Error("Client not initialized");
 
-    const collectionMint = await this.getCurrentCollectionForMint(mint);
+    // updates resolve against the offset tiers version of the nft, not the latest one
+    const { offsetMetadata, offsetTiers, levels } = await this.getNftTiers(
+      mint
+    );
+    const collectionMint =
+      levels[offsetMetadata.currentLevelIndex as number].collectionMint;
     const collectionMetadata = this.getMetadataAddress(collectionMint);
     const collectionMasterEdition =
       this.getMasterEditionAddress(collectionMint);
 
-    const newCollectionMint = await this.getUpdateCollectionForOffset(offset);
-    if (newCollectionMint === null)
-      throw new Error("No levels in offset tiers");
+    const newLevelIndex = this.getNewLevelIndex(
+      offsetMetadata,
+      offset,
+      principal,
+      levels
+    );
+    const newCollectionMint = levels[newLevelIndex].collectionMint;
     const newCollectionMetadata = this.getMetadataAddress(newCollectionMint);
     const newCollectionMasterEdition =
       this.getMasterEditionAddress(newCollectionMint);
@@ -432,48 +927,108 @@ export class ImpactNftClient {
       newCollectionMetadata,
       newCollectionMasterEdition,
       tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      offsetTiers,
+      levelCounts: this.getLevelCountsAddress(offsetTiers),
     };
   }
 
-  private async getCurrentCollectionForMint(
-    mint: PublicKey
-  ): Promise<PublicKey> {
+  /** The offset metadata of the nft, and the offset tiers version it was resolved against */
+  private async getNftTiers(mint: PublicKey) {
     if (!this.stateAddress || !this.tiers)
       throw new Error("Client not initialized");
 
-    let offsetMetadata = this.getOffsetMetadataAddress(mint);
-    let index = await this.program.account.offsetMetadata
-      .fetch(offsetMetadata)
-      .then((res) => res.currentLevelIndex);
+    const offsetMetadata = await this.program.account.offsetMetadata.fetch(
+      this.getOffsetMetadataAddress(mint)
+    );
+    const offsetTiers = this.getOffsetTiersAddress(
+      offsetMetadata.tiersVersion as number
+    );
+    const tiers = await this.program.account.offsetTiers.fetch(offsetTiers);
+    const levels = withIndex(tiers.levels as RawLevel[]);
+
+    return { offsetMetadata, offsetTiers, levels };
+  }
 
-    let levels = this.tiers.levels as Level[];
+  /** The collection of the nft, in the offset tiers version it was resolved against */
+  private async getCurrentCollectionForMint(
+    mint: PublicKey
+  ): Promise<{ collectionMint: PublicKey; offsetTiers: PublicKey }> {
+    const { offsetMetadata, offsetTiers, levels } = await this.getNftTiers(
+      mint
+    );
+
+    return {
+      collectionMint: levels[offsetMetadata.currentLevelIndex as number]
+        .collectionMint,
+      offsetTiers,
+    };
+  }
 
-    return levels[index].collectionMint;
+  /**
+   * The level a new nft is minted into: the level of its offset and principal,
+   * as far as it can be reached without time spent at the levels below
+   */
+  public getMintLevel(offset: anchor.BN, principal: anchor.BN): Level {
+    const offsetIndex = this.getLevelForOffset(offset, principal)?.index ?? 0;
+    let index = 0;
+    while (
+      index < offsetIndex &&
+      this.levels[index + 1].minDurationAtPreviousLevel.isZero()
+    )
+      index++;
+    return this.levels[index];
   }
 
-  // Mirrors offsetTiers.getLevel()
-  private async getUpdateCollectionForOffset(
-    offset: anchor.BN
-  ): Promise<PublicKey | null> {
-    const level = this.getLevelForOffset(offset);
-    return level?.collectionMint || null;
+  // Mirrors calculate_new_level_index in update_nft
+  private getNewLevelIndex(
+    offsetMetadata: {
+      currentLevelIndex: number;
+      highestLevelIndex: number;
+      levelReachedAt: anchor.BN;
+    },
+    offset: anchor.BN,
+    principal: anchor.BN,
+    levels: Level[] = this.levels
+  ): number {
+    if (!this.config) throw new Error("Client not initialized");
+    const currentIndex = offsetMetadata.currentLevelIndex;
+    const offsetIndex =
+      this.getLevelForOffset(offset, principal, levels)?.index ?? 0;
+
+    let targetIndex = offsetIndex;
+    const policy = this.config.downgradePolicy;
+    // the program rejects the downgrade, the nft would keep its level
+    if ("forbid" in policy && offsetIndex < currentIndex) return currentIndex;
+    // with the keepHighest policy, nfts never move below the highest level they reached
+    if ("keepHighest" in policy)
+      targetIndex = Math.max(offsetIndex, offsetMetadata.highestLevelIndex);
+    if (targetIndex <= currentIndex) return targetIndex;
+
+    // promotions are capped by the time spent at the previous level
+    const timeAtCurrentLevel =
+      Date.now() / 1000 - offsetMetadata.levelReachedAt.toNumber();
+    let reachedIndex = currentIndex;
+    for (let index = currentIndex + 1; index <= targetIndex; index++) {
+      const timeAtPreviousLevel =
+        index === currentIndex + 1 ? timeAtCurrentLevel : 0;
+      if (
+        timeAtPreviousLevel <
+        levels[index].minDurationAtPreviousLevel.toNumber()
+      )
+        break;
+      reachedIndex = index;
+    }
+    return reachedIndex;
   }
 
   public get levels(): Level[] {
     if (!this.tiers) throw new Error("Client not initialized");
-    const rawLevels = this.tiers.levels as RawLevel[];
-
-    // add the index to each level
-    return rawLevels.map((level, index) => ({
-      ...level,
-      index,
-    }));
+    return withIndex(this.tiers.levels as RawLevel[]);
   }
 
-  private async getCollectionAccounts() {
+  private async getCollectionAccounts(level: Level) {
     if (!this.tiers) throw new Error("Client not initialized");
-    const levels = this.tiers.levels as Level[];
-    const collectionMint = levels[0].collectionMint;
+    const collectionMint = level.collectionMint;
     const collectionMetadata = this.getMetadataAddress(collectionMint);
     const collectionMasterEdition =
       this.getMasterEditionAddress(collectionMint);
@@ -490,9 +1045,14 @@ export class ImpactNftClient {
     mintAuthority: Keypair,
     user: PublicKey,
     initialOffset: BN,
-    principal: BN
+    principal: BN,
+    maxFeePayable: BN = NO_MAX_FEE_PAYABLE
   ) {
-    const mintNftAccounts = await this.getMintNftAccounts(mint.publicKey, user);
+    const mintNftAccounts = await this.getMintNftAccounts(
+      mint.publicKey,
+      user,
+      this.getMintLevel(initialOffset, principal)
+    );
     const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({
       units: 300000,
     });
@@ -511,7 +1071,7 @@ export class ImpactNftClient {
     };
 
     return this.program.methods
-      .mintNft(initialOffset, principal)
+      .mintNft(initialOffset, principal, maxFeePayable)
       .accounts(accounts)
       .preInstructions([modifyComputeUnits])
       .signers([mint, mintAuthority])
@@ -522,24 +1082,323 @@ export class ImpactNftClient {
     mint: Keypair,
     mintAuthority: Keypair,
     user: PublicKey,
-    updatedOffset: BN
+    updatedOffset: BN,
+    principal: BN,
+    maxFeePayable: BN = NO_MAX_FEE_PAYABLE
   ) {
     const accounts = await this.getMintNftAccounts(mint.publicKey, user);
     const updateAccounts = await this.getUpdateNftAccounts(
       mint.publicKey,
-      updatedOffset
+      updatedOffset,
+      principal
+    );
+    // level-ups are charged the new level's fee, or the update fee rather than the mint fee.
+    // The new level follows the downgrade policy and promotion durations, like update_nft
+    const { offsetMetadata, levels } = await this.getNftTiers(mint.publicKey);
+    const newLevelIndex = this.getNewLevelIndex(
+      offsetMetadata,
+      updatedOffset,
+      principal,
+      levels
+    );
+    const levelUp = newLevelIndex > (offsetMetadata.currentLevelIndex as number);
+    const feeAccounts = await this.getFeeAccounts(
+      levelUp ? levels[newLevelIndex].fee ?? this.config?.updateFee : undefined
     );
 
     return this.program.methods
-      .updateNft(updatedOffset)
+      .updateNft(updatedOffset, principal, maxFeePayable)
       .accounts({
         ...accounts,
         ...updateAccounts,
+        ...feeAccounts,
         globalState: this.stateAddress,
         mint: mint.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        // used to expand the {owner} placeholder in level uris
+        ownerTokenAccount: accounts.userTokenAccount,
       })
       .signers([mintAuthority])
       .rpc();
   }
+
+  /**
+   * Updates several nfts, resolved against the same offset tiers version, in one transaction.
+   * The program stops once the compute budget runs low and returns the number of nfts it updated,
+   * so cranks should resend the remaining updates
+   */
+  public async batchUpdateNfts(
+    mintAuthority: Keypair,
+    updates: {
+      mint: PublicKey;
+      owner?: PublicKey;
+      offset: BN;
+      principal: BN;
+    }[],
+    maxFeePayable: BN = NO_MAX_FEE_PAYABLE,
+    computeUnits = 1_400_000
+  ): Promise<{ updated: number; computeUnitsConsumed?: number }> {
+    if (!this.stateAddress || !this.config)
+      throw new Error("Client not initialized");
+    if (updates.length === 0) throw new Error("No nfts to update");
+
+    const meta = (pubkey: PublicKey, isWritable = false): AccountMeta => ({
+      pubkey,
+      isSigner: false,
+      isWritable,
+    });
+    let offsetTiers: PublicKey | undefined;
+    const remainingAccounts: AccountMeta[] = [];
+    for (const { mint, owner, offset, principal } of updates) {
+      const updateAccounts = await this.getUpdateNftAccounts(
+        mint,
+        offset,
+        principal
+      );
+      offsetTiers = updateAccounts.offsetTiers;
+      remainingAccounts.push(
+        meta(this.getOffsetMetadataAddress(mint), true),
+        meta(mint),
+        meta(this.getMetadataAddress(mint), true),
+        // the program id stands in for a missing owner token account
+        meta(
+          owner ? getAssociatedTokenAddressSync(mint, owner, true) : PROGRAM_ID
+        ),
+        meta(updateAccounts.collectionMint),
+        meta(updateAccounts.collectionMetadata, true),
+        meta(updateAccounts.collectionMasterEdition),
+        meta(updateAccounts.newCollectionMint),
+        meta(updateAccounts.newCollectionMetadata, true),
+        meta(updateAccounts.newCollectionMasterEdition)
+      );
+    }
+    const feeAccounts = await this.getFeeAccounts(
+      this.config.updateFee
+    );
+
+    const signature = await this.program.methods
+      .batchUpdateNfts(
+        updates.map(({ offset, principal }) => ({
+          offsetAmount: offset,
+          principal,
+        })),
+        maxFeePayable
+      )
+      .accounts({
+        payer: this.provider.publicKey,
+        adminMintAuthority: mintAuthority.publicKey,
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+        globalState: this.stateAddress,
+        offsetTiers,
+        levelCounts: offsetTiers && this.getLevelCountsAddress(offsetTiers),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        ...feeAccounts,
+      })
+      .remainingAccounts(remainingAccounts)
+      .preInstructions([
+        ComputeBudgetProgram.setComputeUnitLimit({ units: computeUnits }),
+      ])
+      .signers([mintAuthority])
+      .rpc({ commitment: "confirmed" });
+
+    // the number of nfts updated is the u16 returned by the program
+    const transaction = await this.provider.connection.getTransaction(
+      signature,
+      { commitment: "confirmed", maxSupportedTransactionVersion: 0 }
+    );
+    const returnData = transaction?.meta?.returnData;
+    if (!returnData) throw new Error("No return data from batch update");
+    return {
+      updated: Buffer.from(returnData.data[0], "base64").readUInt16LE(0),
+      computeUnitsConsumed: transaction?.meta?.computeUnitsConsumed,
+    };
+  }
+
+  /** Publishes a new version of the offset tiers, and switches the client to it */
+  public async createOffsetTiersVersion(levels: RawLevel[]) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    const version = this.state.tiersVersion as number;
+    await this.program.methods
+      .createOffsetTiersVersion({ levels })
+      .accounts({
+        adminUpdateAuthority: this.provider.publicKey,
+        payer: this.provider.publicKey,
+        globalState: this.stateAddress,
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+        previousOffsetTiers: this.getOffsetTiersAddress(version),
+        offsetTiers: this.getOffsetTiersAddress(version + 1),
+        levelCounts: this.getLevelCountsAddress(
+          this.getOffsetTiersAddress(version + 1)
+        ),
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(this.getCollectionVerificationAccounts(levels))
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+
+    await this.init(this.stateAddress);
+  }
+
+  /** Re-resolves an nft against the latest version of the offset tiers */
+  public async migrateNft(mint: PublicKey) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    const offsetMetadata = this.getOffsetMetadataAddress(mint);
+    const nft = await this.getNftTiers(mint);
+    const { offsetTiers } = nft;
+    const collectionMint =
+      nft.levels[nft.offsetMetadata.currentLevelIndex as number].collectionMint;
+    // level indices do not carry over between versions, the nft is placed by its thresholds alone
+    const newLevel =
+      this.getLevelForOffset(
+        nft.offsetMetadata.offset as BN,
+        nft.offsetMetadata.principal as BN
+      ) ?? this.levels[0];
+    const newCollectionMint = newLevel.collectionMint;
+
+    return this.program.methods
+      .migrateNft()
+      .accounts({
+        payer: this.provider.publicKey,
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+        globalState: this.stateAddress,
+        offsetTiers,
+        levelCounts: this.getLevelCountsAddress(offsetTiers),
+        newOffsetTiers: this.getOffsetTiersAddress(),
+        newLevelCounts: this.getLevelCountsAddress(),
+        offsetMetadata,
+        mint,
+        metadata: this.getMetadataAddress(mint),
+        newCollectionMint,
+        newCollectionMetadata: this.getMetadataAddress(newCollectionMint),
+        newCollectionMasterEdition:
+          this.getMasterEditionAddress(newCollectionMint),
+        collectionMint,
+        collectionMetadata: this.getMetadataAddress(collectionMint),
+        collectionMasterEdition: this.getMasterEditionAddress(collectionMint),
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        ownerTokenAccount: null,
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  /**
+   * Rewrites the metadata of an nft from the current definition of its level.
+   * The owner is only needed if the level's uri contains the {owner} placeholder
+   */
+  public async refreshMetadata(mint: PublicKey, owner?: PublicKey) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    const { offsetTiers } = await this.getCurrentCollectionForMint(mint);
+
+    return this.program.methods
+      .refreshMetadata()
+      .accounts({
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+        globalState: this.stateAddress,
+        offsetTiers,
+        offsetMetadata: this.getOffsetMetadataAddress(mint),
+        mint,
+        metadata: this.getMetadataAddress(mint),
+        ownerTokenAccount: owner
+          ? getAssociatedTokenAddressSync(mint, owner, true)
+          : null,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  /**
+   * Rewrites the metadata of several nfts in one transaction.
+   * All nfts must be resolved against the same offset tiers version
+   */
+  public async refreshMetadataBatch(
+    nfts: { mint: PublicKey; owner?: PublicKey }[]
+  ) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+    if (nfts.length === 0) throw new Error("No nfts to refresh");
+
+    const { offsetTiers } = await this.getCurrentCollectionForMint(
+      nfts[0].mint
+    );
+    const remainingAccounts: AccountMeta[] = nfts.flatMap(({ mint, owner }) => [
+      {
+        pubkey: this.getOffsetMetadataAddress(mint),
+        isSigner: false,
+        isWritable: false,
+      },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      {
+        pubkey: this.getMetadataAddress(mint),
+        isSigner: false,
+        isWritable: true,
+      },
+      {
+        // the program id stands in for a missing owner token account
+        pubkey: owner
+          ? getAssociatedTokenAddressSync(mint, owner, true)
+          : PROGRAM_ID,
+        isSigner: false,
+        isWritable: false,
+      },
+    ]);
+
+    return this.program.methods
+      .refreshMetadataBatch()
+      .accounts({
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+        globalState: this.stateAddress,
+        offsetTiers,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
+
+  /**
+   * Burns an nft held by the provider wallet, closing its offset metadata.
+   * The rent is returned to the rent recipient, which defaults to the owner
+   */
+  public async burnNft(
+    mint: PublicKey,
+    rentRecipient: PublicKey = this.provider.publicKey
+  ) {
+    if (!this.stateAddress) throw new Error("Client not initialized");
+
+    const { collectionMint, offsetTiers } =
+      await this.getCurrentCollectionForMint(mint);
+
+    return this.program.methods
+      .burnNft()
+      .accounts({
+        owner: this.provider.publicKey,
+        rentRecipient,
+        tokenAuthority: this.getTokenAuthorityAddress(this.stateAddress),
+        globalState: this.stateAddress,
+        offsetTiers,
+        levelCounts: this.getLevelCountsAddress(offsetTiers),
+        offsetMetadata: this.getOffsetMetadataAddress(mint),
+        mint,
+        metadata: this.getMetadataAddress(mint),
+        masterEdition: this.getMasterEditionAddress(mint),
+        ownerTokenAccount: getAssociatedTokenAddressSync(
+          mint,
+          this.provider.publicKey,
+          true
+        ),
+        collectionMint,
+        collectionMetadata: this.getMetadataAddress(collectionMint),
+        collectionMasterEdition: this.getMasterEditionAddress(collectionMint),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .rpc()
+      .then(() => confirm(this.provider.connection));
+  }
 }
@@ -1,4 +1,4 @@
-import { ImpactNftClient, setUpAnchor } from "../client/src";
+import { ImpactNftClient, RawLevel, setUpAnchor } from "../client/src";
 import { PublicKey } from "@solana/web3.js";
 import meta from "./impactNFTLevels.json";
 import BN from "bn.js";
@@ -24,18 +24,22 @@ const stateAddress =
 
   console.log("Creating collection mints...");
   const levels = await Promise.all(
-    meta.map(async ({ uri, name, symbol, offset }, i) => {
+    meta.map(async ({ uri, name, symbol, offset }, i): Promise<RawLevel> => {
       console.log(`Creating collection for level ${i}...`);
       const mint = await client.createCollectionMint(
         uri,
         `Sunrise Impact Collection ${i}`
       );
+      // levels are reached by offset alone, the token authority is the sole creator
       return {
         offset: new BN(offset),
+        minPrincipal: new BN(0),
+        minDurationAtPreviousLevel: new BN(0),
         uri,
         name,
         symbol,
         collectionMint: mint.publicKey,
+        creators: [],
       };
     })
   );
@@ -47,6 +51,6 @@ const stateAddress =
   await client.addLevelsToOffsetTiers(levels.slice(4, 8));
 
   console.log("Registering offset tiers 8-9...");
-  await client.addLevelsToOffsetTiers(levels.slice(9));
+  await client.addLevelsToOffsetTiers(levels.slice(8));
   console.log("Done! State address: " + client.stateAddress);
 })();
@@ -4,7 +4,27 @@ import { ImpactNft } from "../client/src/types/impact_nft";
 import { expect, assert } from "chai";
 import BN from "bn.js";
 import { ImpactNftClient, Level } from "../client/src";
-import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
+import {
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  sendAndConfirmTransaction,
+  SystemProgram,
+  Transaction,
+} from "@solana/web3.js";
+import {
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createMint,
+  ExtensionType,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getMintLen,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
 import { getTestMetadata } from "./util";
 
 const program = anchor.workspace.ImpactNft as Program<ImpactNft>;
@@ -25,10 +45,13 @@ const makeTestLevels = async (client: ImpactNftClient): Promise<Level[]> => {
     );
     const level: Level = {
       offset: new BN(i).muln(100),
+      minPrincipal: new BN(0),
+      minDurationAtPreviousLevel: new BN(0),
       uri: metadata[i],
       name: `sunriseStake${i}`,
       symbol: `sun${i}`,
       collectionMint: mint.publicKey,
+      creators: [],
       index: i,
     };
     levels.push(level);
@@ -43,6 +66,16 @@ const makeTestLevels = async (client: ImpactNftClient): Promise<Level[]> => {
   return levels;
 };
 
+const expectAnchorError = async (promise: Promise<unknown>, code: string) => {
+  try {
+    await promise;
+  } catch (e) {
+    expect(e.error?.errorCode?.code).to.equal(code);
+    return;
+  }
+  assert.fail(`Expected ${code}`);
+};
+
 describe("impact-nft", () => {
   let client: ImpactNftClient;
   let user = Keypair.generate();
@@ -163,7 +196,13 @@ describe("impact-nft", () => {
   });
 
   it("can update an nft", async () => {
-    await client.updateNft(mint, mintAuthority, user.publicKey, updatedOffset);
+    await client.updateNft(
+      mint,
+      mintAuthority,
+      user.publicKey,
+      updatedOffset,
+      principal
+    );
 
     const offsetMetadataAddress = client.getOffsetMetadataAddress(
       mint.publicKey
@@ -173,10 +212,94 @@ describe("impact-nft", () => {
       offsetMetadataAddress
     );
     assert(offsetMetadata.offset.eq(updatedOffset));
+    expect(offsetMetadata.updateCount.toNumber()).to.equal(1);
 
     // TODO: Find a way to validate that the mpl metadata is indeed updated
   });
 
+  it("records the offset of an update that does not change the level", async () => {
+    const sameLevelOffset = updatedOffset.addn(1);
+    await client.updateNft(
+      mint,
+      mintAuthority,
+      user.publicKey,
+      sameLevelOffset,
+      principal
+    );
+
+    const offsetMetadata = await program.account.offsetMetadata.fetch(
+      client.getOffsetMetadataAddress(mint.publicKey)
+    );
+    assert(offsetMetadata.offset.eq(sameLevelOffset));
+    expect(offsetMetadata.updateCount.toNumber()).to.equal(2);
+    expect(offsetMetadata.lastUpdated.toNumber()).to.be.greaterThan(0);
+  });
+
+  context("batch updates", () => {
+    const mints = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+    let computeUnitsPerUpdate: number;
+
+    const getLevelIndex = async (mint: Keypair) =>
+      program.account.offsetMetadata
+        .fetch(client.getOffsetMetadataAddress(mint.publicKey))
+        .then((offsetMetadata) => offsetMetadata.currentLevelIndex as number);
+
+    before("mint the nfts at level 0", async () => {
+      for (const mint of mints) {
+        await client.mintNft(
+          mint,
+          mintAuthority,
+          user.publicKey,
+          initialOffset,
+          principal
+        );
+      }
+    });
+
+    it("updates every nft within the compute budget", async () => {
+      const { updated, computeUnitsConsumed } = await client.batchUpdateNfts(
+        mintAuthority,
+        mints.map((mint) => ({
+          mint: mint.publicKey,
+          offset: level2Offset,
+          principal,
+        }))
+      );
+      expect(updated).to.equal(mints.length);
+      for (const mint of mints) expect(await getLevelIndex(mint)).to.equal(2);
+
+      computeUnitsPerUpdate = Math.ceil(computeUnitsConsumed / mints.length);
+      console.log("Compute units per level change:", computeUnitsPerUpdate);
+    });
+
+    it("returns the number of nfts updated when the compute budget runs out", async () => {
+      const updates = mints.map((mint) => ({
+        mint: mint.publicKey,
+        offset: aboveHighestOffset,
+        principal,
+      }));
+      // enough for one update, but not for the reserve of a second one
+      const { updated } = await client.batchUpdateNfts(
+        mintAuthority,
+        updates,
+        undefined,
+        Math.ceil(computeUnitsPerUpdate * 1.7)
+      );
+      expect(updated).to.equal(1);
+      expect(await getLevelIndex(mints[0])).to.equal(5);
+      expect(await getLevelIndex(mints[1])).to.equal(2);
+      expect(await getLevelIndex(mints[2])).to.equal(2);
+
+      // the crank resends the remaining updates
+      const { updated: remaining } = await client.batchUpdateNfts(
+        mintAuthority,
+        updates.slice(updated)
+      );
+      expect(remaining).to.equal(2);
+      for (const mint of mints) expect(await getLevelIndex(mint)).to.equal(5);
+    });
+  });
+
   context("with sol fees", () => {
     const user = Keypair.generate();
     const mint = Keypair.generate();
@@ -201,28 +324,35 @@ describe("impact-nft", () => {
     });
 
     it("Can register a new global state with SOL fees", async () => {
-      const levels = await makeTestLevels(client);
       const feeConfig = {
         fee: feeBasisPoints,
-        recipient: feeRecipient.publicKey,
         feeType: { percentage: {} }, // alt: { fixed: {} }
         coinType: { native: {} }, // alt: { spl: {} }
         splTokenMint: null,
+        minFee: new BN(0),
+        maxFee: null,
+      };
+      const feeDistribution = {
+        recipients: [{ address: feeRecipient.publicKey, share: 10_000 }],
+        primaryRecipient: 0,
       };
       client = await ImpactNftClient.register(
         mintAuthority.publicKey,
-        levels.length,
-        feeConfig
+        getTestMetadata().length,
+        feeConfig,
+        feeDistribution
       );
+      // collections must be created after registering, so that the new token authority controls them
+      const levels = await makeTestLevels(client);
       await client.registerOffsetTiers(levels.slice(0, 5));
+      await client.createFeeVault(null);
 
       const { state } = client.details();
       expect(state.fee.fee.toNumber()).to.equal(feeConfig.fee.toNumber());
       expect(state.fee.feeType).to.deep.equal({ percentage: {} });
     });
 
-    // re-enable once fees are supported
-    it.skip("Can mint an nft and charge a fee", async () => {
+    it("Can mint an nft and charge a fee", async () => {
       await client.mintNft(
         mint,
         mintAuthority,
@@ -231,6 +361,9 @@ describe("impact-nft", () => {
         principal
       );
 
+      // fees are collected in the fee vault until withdrawn
+      await client.withdrawFees(null);
+
       // check if the fee was paid
       const value = await program.provider.connection.getBalance(
         feeRecipient.publicKey
@@ -240,5 +373,767 @@ describe("impact-nft", () => {
           principal.toNumber() * (feeBasisPoints.toNumber() / 10_000)
       ); // 1% of the principal
     });
+
+    it("rejects a fee above the maximum fee payable", async () => {
+      await expectAnchorError(
+        client.mintNft(
+          Keypair.generate(),
+          mintAuthority,
+          user.publicKey,
+          initialOffset,
+          principal,
+          new BN(0)
+        ),
+        "FeeExceedsMaximum"
+      );
+    });
+
+    it("discounts the fee of a payer with a fee waiver", async () => {
+      const largePrincipal = new BN(1_000_000); // a 10_000 lamport fee
+      const vault = client.getFeeVaultAddress(null);
+      await client.addFeeWaiver(client.provider.publicKey, 2_500);
+
+      const balanceBefore = await program.provider.connection.getBalance(vault);
+      // the maximum fee payable applies to the discounted fee
+      await client.mintNft(
+        Keypair.generate(),
+        mintAuthority,
+        user.publicKey,
+        initialOffset,
+        largePrincipal,
+        new BN(7_500)
+      );
+      const balanceAfter = await program.provider.connection.getBalance(vault);
+      expect(balanceAfter - balanceBefore).to.equal(7_500);
+
+      await client.removeFeeWaiver(client.provider.publicKey);
+    });
   });
+
+  context("fee updates", () => {
+    let client: ImpactNftClient;
+    const connection = program.provider.connection;
+    const feeRecipient = Keypair.generate();
+    const fixedFee = (lamports: number) => ({
+      fee: new BN(lamports),
+      feeType: { fixed: {} },
+      coinType: { native: {} },
+      splTokenMint: null,
+      minFee: new BN(0),
+      maxFee: null,
+    });
+    const feeDistribution = {
+      recipients: [{ address: feeRecipient.publicKey, share: 10_000 }],
+      primaryRecipient: 0,
+    };
+    const getVaultBalance = () =>
+      connection.getBalance(client.getFeeVaultAddress(null));
+    const mintAndGetFeePaid = async () => {
+      const balanceBefore = await getVaultBalance();
+      await client.mintNft(
+        Keypair.generate(),
+        mintAuthority,
+        user.publicKey,
+        initialOffset,
+        principal
+      );
+      return (await getVaultBalance()) - balanceBefore;
+    };
+
+    before("register a global state with a fixed SOL fee", async () => {
+      client = await ImpactNftClient.register(
+        mintAuthority.publicKey,
+        3,
+        fixedFee(1_000),
+        feeDistribution
+      );
+      const levels = await makeTestLevels(client);
+      await client.registerOffsetTiers(levels.slice(0, 3));
+      await client.createFeeVault(null);
+    });
+
+    it("rejects invalid fee configs", async () => {
+      await expectAnchorError(
+        client.updateGlobalState({
+          fee: { ...fixedFee(10_001), feeType: { percentage: {} } },
+        }),
+        "InvalidFeeBasisPoints"
+      );
+      await expectAnchorError(
+        client.updateGlobalState({
+          fee: { ...fixedFee(100), minFee: new BN(10), maxFee: new BN(5) },
+        }),
+        "InvalidFeeConfig"
+      );
+      await expectAnchorError(
+        client.updateGlobalState({
+          fee: { ...fixedFee(100), splTokenMint: Keypair.generate().publicKey },
+        }),
+        "InvalidFeeMint"
+      );
+      await expectAnchorError(
+        client.updateGlobalState({ feeDistribution: null }),
+        "NoFeeDistribution"
+      );
+      expect(client.state.fee.fee.toNumber()).to.equal(1_000);
+    });
+
+    it("applies a scheduled fee change from its activation slot", async () => {
+      const activationSlot = (await connection.getSlot()) + 20;
+      await client.updateGlobalState({
+        fee: fixedFee(2_000),
+        feeActivationSlot: new BN(activationSlot),
+      });
+
+      expect(client.state.fee.fee.toNumber()).to.equal(1_000);
+      expect(client.state.pendingFeeUpdate.activationSlot.toNumber()).to.equal(
+        activationSlot
+      );
+      expect(await mintAndGetFeePaid()).to.equal(1_000);
+
+      while ((await connection.getSlot()) <= activationSlot) {
+        await new Promise((resolve) => setTimeout(resolve, 400));
+      }
+      expect(await mintAndGetFeePaid()).to.equal(2_000);
+    });
+
+    it("keeps the fee distribution while a scheduled change leaves a fee active", async () => {
+      await expectAnchorError(
+        client.updateGlobalState({
+          fee: null,
+          feeDistribution: null,
+          feeActivationSlot: new BN((await connection.getSlot()) + 1_000),
+        }),
+        "FeeDistributionInUse"
+      );
+    });
+
+    it("keeps the fee distribution while a level charges a fee", async () => {
+      await client.updateGlobalState({ fee: null });
+      await client.updateLevel(0, { ...client.levels[0], fee: fixedFee(500) });
+
+      await expectAnchorError(
+        client.updateGlobalState({ feeDistribution: null }),
+        "FeeDistributionInUse"
+      );
+
+      await client.updateLevel(0, { ...client.levels[0], fee: null });
+      await client.updateGlobalState({ feeDistribution: null });
+      expect(client.state.feeDistribution).to.equal(null);
+    });
+  });
+
+  for (const [name, tokenProgram, transferFeeBasisPoints] of [
+    ["SPL Token", TOKEN_PROGRAM_ID, 0],
+    ["Token-2022 transfer fee", TOKEN_2022_PROGRAM_ID, 100],
+  ] as const) {
+    context(`with ${name} fees`, () => {
+      let client: ImpactNftClient;
+      let feeMint: PublicKey;
+      const connection = program.provider.connection;
+      const payer = (program.provider as anchor.AnchorProvider).wallet
+        .payer as Keypair;
+      const fee = 1_000;
+
+      const getTokenBalance = async (owner: PublicKey) =>
+        getAccount(
+          connection,
+          getAssociatedTokenAddressSync(feeMint, owner, true, tokenProgram),
+          undefined,
+          tokenProgram
+        ).then((account) => Number(account.amount));
+
+      // a Token-2022 mint withholds a share of every transfer
+      const createFeeMint = async () => {
+        if (transferFeeBasisPoints === 0) {
+          return createMint(
+            connection,
+            payer,
+            payer.publicKey,
+            null,
+            0,
+            undefined,
+            undefined,
+            tokenProgram
+          );
+        }
+        const mint = Keypair.generate();
+        const space = getMintLen([ExtensionType.TransferFeeConfig]);
+        const transaction = new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: mint.publicKey,
+            space,
+            lamports: await connection.getMinimumBalanceForRentExemption(space),
+            programId: tokenProgram,
+          }),
+          createInitializeTransferFeeConfigInstruction(
+            mint.publicKey,
+            payer.publicKey,
+            payer.publicKey,
+            transferFeeBasisPoints,
+            BigInt(1_000_000),
+            tokenProgram
+          ),
+          createInitializeMintInstruction(
+            mint.publicKey,
+            0,
+            payer.publicKey,
+            null,
+            tokenProgram
+          )
+        );
+        await sendAndConfirmTransaction(connection, transaction, [payer, mint]);
+        return mint.publicKey;
+      };
+
+      before("register a fixed token fee and fund the payer", async () => {
+        feeMint = await createFeeMint();
+        const payerTokenAccount = await getOrCreateAssociatedTokenAccount(
+          connection,
+          payer,
+          feeMint,
+          payer.publicKey,
+          false,
+          undefined,
+          undefined,
+          tokenProgram
+        );
+        await mintTo(
+          connection,
+          payer,
+          feeMint,
+          payerTokenAccount.address,
+          payer,
+          1_000_000,
+          [],
+          undefined,
+          tokenProgram
+        );
+
+        client = await ImpactNftClient.register(
+          mintAuthority.publicKey,
+          3,
+          {
+            fee: new BN(fee),
+            feeType: { fixed: {} },
+            coinType: { spl: {} },
+            splTokenMint: feeMint,
+            minFee: new BN(0),
+            maxFee: null,
+          },
+          {
+            recipients: [{ address: Keypair.generate().publicKey, share: 10_000 }],
+            primaryRecipient: 0,
+          }
+        );
+        const levels = await makeTestLevels(client);
+        await client.registerOffsetTiers(levels.slice(0, 3));
+        await client.createFeeVault(feeMint, tokenProgram);
+      });
+
+      it("charges the fee to the payer rather than the nft owner", async () => {
+        // the owner holds no fee tokens
+        const owner = Keypair.generate().publicKey;
+        const payerBalanceBefore = await getTokenBalance(payer.publicKey);
+
+        await client.mintNft(
+          Keypair.generate(),
+          mintAuthority,
+          owner,
+          initialOffset,
+          principal
+        );
+
+        // the vault receives the whole fee, the payer covers any transfer fee on top
+        const vault = client.getFeeVaultAddress(feeMint);
+        expect(await getTokenBalance(vault)).to.equal(fee);
+        const paid = payerBalanceBefore - (await getTokenBalance(payer.publicKey));
+        const transferFee = Math.ceil(
+          (fee * transferFeeBasisPoints) / (10_000 - transferFeeBasisPoints)
+        );
+        expect(paid).to.equal(fee + transferFee);
+      });
+    });
+  }
+
+  for (const updateFeeScaling of [{ flat: {} }, { perLevel: {} }] as const) {
+    const scaling = Object.keys(updateFeeScaling)[0];
+    context(`with a ${scaling} update fee`, () => {
+      let client: ImpactNftClient;
+      const mint = Keypair.generate();
+      const updateFee = 1_000;
+
+      const getVaultBalance = () =>
+        program.provider.connection.getBalance(client.getFeeVaultAddress(null));
+      const updateAndGetFeePaid = async (offset: BN) => {
+        const balanceBefore = await getVaultBalance();
+        await client.updateNft(mint, mintAuthority, user.publicKey, offset, principal);
+        return (await getVaultBalance()) - balanceBefore;
+      };
+
+      before("register an update fee and mint an nft", async () => {
+        client = await ImpactNftClient.register(
+          mintAuthority.publicKey,
+          4,
+          undefined,
+          {
+            recipients: [{ address: Keypair.generate().publicKey, share: 10_000 }],
+            primaryRecipient: 0,
+          },
+          {
+            fee: new BN(updateFee),
+            feeType: { fixed: {} },
+            coinType: { native: {} },
+            splTokenMint: null,
+            minFee: new BN(0),
+            maxFee: null,
+          },
+          updateFeeScaling
+        );
+        const levels = await makeTestLevels(client);
+        await client.registerOffsetTiers(levels.slice(0, 4));
+        await client.createFeeVault(null);
+        await client.mintNft(
+          mint,
+          mintAuthority,
+          user.publicKey,
+          initialOffset,
+          principal
+        );
+      });
+
+      it("charges nothing for an update within the level", async () => {
+        expect(await updateAndGetFeePaid(initialOffset.addn(10))).to.equal(0);
+      });
+
+      it("charges the update fee on a level-up", async () => {
+        // two levels up, from level 0 to level 2
+        const expectedFee = scaling === "flat" ? updateFee : 2 * updateFee;
+        expect(await updateAndGetFeePaid(level2Offset)).to.equal(expectedFee);
+      });
+
+      it("charges nothing for a level-down", async () => {
+        expect(await updateAndGetFeePaid(initialOffset)).to.equal(0);
+      });
+    });
+  }
+
+  context("with per-level fees", () => {
+    let client: ImpactNftClient;
+    const mint = Keypair.generate();
+    const fixedFee = (lamports: number) => ({
+      fee: new BN(lamports),
+      feeType: { fixed: {} },
+      coinType: { native: {} },
+      splTokenMint: null,
+      minFee: new BN(0),
+      maxFee: null,
+    });
+
+    const getVaultBalance = () =>
+      program.provider.connection.getBalance(client.getFeeVaultAddress(null));
+    const getFeePaid = async (transaction: () => Promise<unknown>) => {
+      const balanceBefore = await getVaultBalance();
+      await transaction();
+      return (await getVaultBalance()) - balanceBefore;
+    };
+    const mintAt = (nftMint: Keypair, offset: BN) => () =>
+      client.mintNft(nftMint, mintAuthority, user.publicKey, offset, principal);
+
+    before("register a free entry level and a level with its own fee", async () => {
+      client = await ImpactNftClient.register(
+        mintAuthority.publicKey,
+        3,
+        fixedFee(1_000),
+        {
+          recipients: [{ address: Keypair.generate().publicKey, share: 10_000 }],
+          primaryRecipient: 0,
+        },
+        fixedFee(1_000)
+      );
+      const levels = (await makeTestLevels(client)).slice(0, 3);
+      levels[0].fee = fixedFee(0);
+      levels[1].fee = fixedFee(5_000);
+      await client.registerOffsetTiers(levels);
+      await client.createFeeVault(null);
+    });
+
+    it("mints into the entry level for free", async () => {
+      expect(await getFeePaid(mintAt(mint, initialOffset))).to.equal(0);
+    });
+
+    it("charges the fee of the level minted into", async () => {
+      expect(await getFeePaid(mintAt(Keypair.generate(), updatedOffset))).to.equal(
+        5_000
+      );
+      // levels without an override charge the global fee
+      expect(await getFeePaid(mintAt(Keypair.generate(), level2Offset))).to.equal(
+        1_000
+      );
+    });
+
+    it("charges the fee of the level reached on a level-up", async () => {
+      const feePaid = await getFeePaid(() =>
+        client.updateNft(mint, mintAuthority, user.publicKey, updatedOffset, principal)
+      );
+      expect(feePaid).to.equal(5_000);
+    });
+  });
+
+  context("with per-level royalties and creators", () => {
+    let client: ImpactNftClient;
+    const mint = Keypair.generate();
+    const creator = Keypair.generate().publicKey;
+
+    const getNft = () =>
+      client.metaplex
+        .nfts()
+        .findByMint({ mintAddress: mint.publicKey, loadJsonMetadata: false });
+    const creatorsOf = (nft: Awaited<ReturnType<typeof getNft>>) =>
+      nft.creators.map(({ address, verified, share }) => ({
+        address: address.toBase58(),
+        verified,
+        share,
+      }));
+
+    before("register a level with royalties and its own creators", async () => {
+      client = await ImpactNftClient.register(mintAuthority.publicKey, 2, null);
+      const levels = (await makeTestLevels(client)).slice(0, 2);
+      levels[0].royaltyBasisPoints = 500;
+      levels[0].creators = [{ address: creator, share: 100 }];
+      await client.registerOffsetTiers(levels);
+      await client.mintNft(
+        mint,
+        mintAuthority,
+        user.publicKey,
+        initialOffset,
+        principal
+      );
+    });
+
+    it("writes the royalties and creators of the level to the metadata", async () => {
+      const nft = await getNft();
+      const tokenAuthority = client.getTokenAuthorityAddress(client.stateAddress);
+
+      expect(nft.sellerFeeBasisPoints).to.equal(500);
+      // the token authority stays a verified creator, without a share
+      expect(creatorsOf(nft)).to.deep.equal([
+        { address: creator.toBase58(), verified: false, share: 100 },
+        { address: tokenAuthority.toBase58(), verified: true, share: 0 },
+      ]);
+    });
+
+    it("replaces them with the defaults of a level without any", async () => {
+      await client.updateNft(
+        mint,
+        mintAuthority,
+        user.publicKey,
+        updatedOffset,
+        principal
+      );
+      const nft = await getNft();
+      const tokenAuthority = client.getTokenAuthorityAddress(client.stateAddress);
+
+      expect(nft.sellerFeeBasisPoints).to.equal(0);
+      expect(creatorsOf(nft)).to.deep.equal([
+        { address: tokenAuthority.toBase58(), verified: true, share: 100 },
+      ]);
+    });
+  });
+
+  context("nft lifecycle", () => {
+    let client: ImpactNftClient;
+    let levels: Level[];
+    const mint = Keypair.generate();
+    const offset = new BN(250); // above the third level
+
+    const getNft = (mintAddress: PublicKey) =>
+      client.metaplex.nfts().findByMint({ mintAddress, loadJsonMetadata: false });
+    const getCollectionSize = async (collectionMint: PublicKey) =>
+      getNft(collectionMint).then((nft) => nft.collectionDetails.size.toNumber());
+    const getLevelCounts = async (version = 0) =>
+      program.account.levelCounts
+        .fetch(client.getLevelCountsAddress(client.getOffsetTiersAddress(version)))
+        .then((levelCounts) => levelCounts.counts.map((count) => count.toNumber()));
+    const getOffsetMetadata = () =>
+      program.account.offsetMetadata.fetch(
+        client.getOffsetMetadataAddress(mint.publicKey)
+      );
+
+    before("register three levels", async () => {
+      client = await ImpactNftClient.register(mintAuthority.publicKey, 3, null);
+      levels = (await makeTestLevels(client)).slice(0, 3);
+      await client.registerOffsetTiers(levels);
+    });
+
+    it("mints straight into a higher level", async () => {
+      await client.mintNft(
+        mint,
+        mintAuthority,
+        client.provider.publicKey,
+        offset,
+        principal
+      );
+
+      const offsetMetadata = await getOffsetMetadata();
+      expect(offsetMetadata.currentLevelIndex).to.equal(2);
+      expect(offsetMetadata.highestLevelIndex).to.equal(2);
+      expect(await getLevelCounts()).to.deep.equal([0, 0, 1]);
+
+      const nft = await getNft(mint.publicKey);
+      expect(nft.collection.address.toBase58()).to.equal(
+        levels[2].collectionMint.toBase58()
+      );
+      expect(nft.collection.verified).to.be.true;
+      expect(await getCollectionSize(levels[2].collectionMint)).to.equal(1);
+    });
+
+    it("rejects collections the token authority cannot verify", async () => {
+      // the update authority stays with the provider wallet
+      const { nft: foreignCollection } = await client.metaplex.nfts().create({
+        uri: levels[0].uri,
+        name: "foreignCollection",
+        sellerFeeBasisPoints: 0,
+        isCollection: true,
+      });
+      await expectAnchorError(
+        client.insertLevel(3, {
+          ...levels[2],
+          offset: new BN(300),
+          collectionMint: foreignCollection.address,
+        }),
+        "InvalidCollectionAuthority"
+      );
+
+      const { nft: unsizedNft } = await client.metaplex.nfts().create({
+        uri: levels[0].uri,
+        name: "notACollection",
+        sellerFeeBasisPoints: 0,
+      });
+      await expectAnchorError(
+        client.insertLevel(3, {
+          ...levels[2],
+          offset: new BN(300),
+          collectionMint: unsizedNft.address,
+        }),
+        "InvalidCollection"
+      );
+    });
+
+    it("rejects edits that would move nfts out of their level", async () => {
+      await expectAnchorError(
+        client.updateLevel(2, {
+          ...levels[2],
+          collectionMint: levels[1].collectionMint,
+        }),
+        "LevelInUse"
+      );
+      await expectAnchorError(client.removeLevel(2), "LevelInUse");
+      await expectAnchorError(
+        client.insertLevel(1, { ...levels[1], offset: new BN(50) }),
+        "LevelInUse"
+      );
+    });
+
+    it("inserts, updates and removes levels without nfts", async () => {
+      const collectionMint = await client.createCollectionMint(
+        levels[0].uri,
+        "insertedCollection"
+      );
+      const inserted = {
+        ...levels[2],
+        offset: new BN(300),
+        name: "inserted",
+        collectionMint: collectionMint.publicKey,
+      };
+
+      await client.insertLevel(3, inserted);
+      expect(client.levels.length).to.equal(4);
+      expect(client.levels[3].name).to.equal("inserted");
+      expect(await getLevelCounts()).to.deep.equal([0, 0, 1, 0]);
+
+      await client.updateLevel(3, { ...inserted, name: "updated" });
+      expect(client.levels[3].name).to.equal("updated");
+
+      await client.removeLevel(3);
+      expect(client.levels.length).to.equal(3);
+      expect(await getLevelCounts()).to.deep.equal([0, 0, 1]);
+    });
+
+    it("refreshes the metadata of an nft from its level", async () => {
+      // the collection is unchanged, so the level can be edited while it holds the nft
+      await client.updateLevel(2, { ...levels[2], name: "refreshed" });
+      await client.refreshMetadata(mint.publicKey);
+
+      const nft = await getNft(mint.publicKey);
+      expect(nft.name).to.equal("refreshed");
+    });
+
+    it("migrates an nft to a new version of the offset tiers", async () => {
+      // the offset of the nft falls back to the first level in the new version
+      await client.createOffsetTiersVersion([
+        levels[0],
+        { ...levels[1], offset: new BN(300) },
+        { ...levels[2], offset: new BN(600) },
+      ]);
+      await client.migrateNft(mint.publicKey);
+
+      const offsetMetadata = await getOffsetMetadata();
+      expect(offsetMetadata.tiersVersion).to.equal(1);
+      expect(offsetMetadata.currentLevelIndex).to.equal(0);
+      expect(offsetMetadata.levelReachedAt.toNumber()).to.be.greaterThan(0);
+      expect(await getLevelCounts(0)).to.deep.equal([0, 0, 0]);
+      expect(await getLevelCounts(1)).to.deep.equal([1, 0, 0]);
+
+      const nft = await getNft(mint.publicKey);
+      expect(nft.collection.address.toBase58()).to.equal(
+        levels[0].collectionMint.toBase58()
+      );
+      expect(nft.collection.verified).to.be.true;
+      expect(await getCollectionSize(levels[2].collectionMint)).to.equal(0);
+      expect(await getCollectionSize(levels[0].collectionMint)).to.equal(1);
+    });
+
+    it("burns an nft and closes its offset metadata", async () => {
+      const rentRecipient = Keypair.generate().publicKey;
+      const offsetMetadataAddress = client.getOffsetMetadataAddress(
+        mint.publicKey
+      );
+      const offsetMetadataRent = await program.provider.connection
+        .getAccountInfo(offsetMetadataAddress)
+        .then((account) => account.lamports);
+
+      await client.burnNft(mint.publicKey, rentRecipient);
+
+      expect(
+        await program.provider.connection.getAccountInfo(offsetMetadataAddress)
+      ).to.be.null;
+      expect(
+        await program.provider.connection.getBalance(rentRecipient)
+      ).to.equal(offsetMetadataRent);
+      expect(
+        await program.provider.connection.getAccountInfo(
+          client.getMetadataAddress(mint.publicKey)
+        )
+      ).to.be.null;
+      // the nft is unverified from its collection before it is burned
+      expect(await getCollectionSize(levels[0].collectionMint)).to.equal(0);
+      expect(await getLevelCounts(1)).to.deep.equal([0, 0, 0]);
+    });
+  });
+
+  for (const [name, downgradePolicy] of [
+    ["forbid", { forbid: {} }],
+    ["keepHighest", { keepHighest: {} }],
+  ] as const) {
+    context(`updating under the ${name} downgrade policy`, () => {
+      let client: ImpactNftClient;
+      const mint = Keypair.generate();
+
+      before("mint an nft at the third level", async () => {
+        client = await ImpactNftClient.register(
+          mintAuthority.publicKey,
+          3,
+          undefined,
+          undefined,
+          undefined,
+          undefined,
+          downgradePolicy
+        );
+        await client.registerOffsetTiers(
+          (await makeTestLevels(client)).slice(0, 3)
+        );
+        await client.mintNft(
+          mint,
+          mintAuthority,
+          user.publicKey,
+          level2Offset,
+          principal
+        );
+      });
+
+      it("handles an offset that drops below the level", async () => {
+        const update = client.updateNft(
+          mint,
+          mintAuthority,
+          user.publicKey,
+          initialOffset,
+          principal
+        );
+        if (name === "forbid") {
+          await expectAnchorError(update, "DowngradeForbidden");
+        } else {
+          await update;
+        }
+
+        const offsetMetadata = await program.account.offsetMetadata.fetch(
+          client.getOffsetMetadataAddress(mint.publicKey)
+        );
+        expect(offsetMetadata.currentLevelIndex).to.equal(2);
+        // the offset is only recorded if the update is allowed
+        const expectedOffset = name === "forbid" ? level2Offset : initialOffset;
+        assert((offsetMetadata.offset as BN).eq(expectedOffset));
+      });
+    });
+
+    context(`migrating under the ${name} downgrade policy`, () => {
+      let client: ImpactNftClient;
+      let levels: Level[];
+      const mint = Keypair.generate();
+
+      const getOffsetMetadata = () =>
+        program.account.offsetMetadata.fetch(
+          client.getOffsetMetadataAddress(mint.publicKey)
+        );
+
+      before("mint an nft at the top of three levels", async () => {
+        client = await ImpactNftClient.register(
+          mintAuthority.publicKey,
+          3,
+          undefined,
+          undefined,
+          undefined,
+          undefined,
+          downgradePolicy
+        );
+        levels = (await makeTestLevels(client)).slice(0, 3);
+        await client.registerOffsetTiers(levels);
+        await client.mintNft(
+          mint,
+          mintAuthority,
+          user.publicKey,
+          level2Offset,
+          principal
+        );
+      });
+
+      it("places the nft by its offset in a version with fewer levels", async () => {
+        await client.createOffsetTiersVersion([
+          levels[0],
+          { ...levels[1], offset: new BN(300) },
+        ]);
+        await client.migrateNft(mint.publicKey);
+
+        const offsetMetadata = await getOffsetMetadata();
+        expect(offsetMetadata.tiersVersion).to.equal(1);
+        expect(offsetMetadata.currentLevelIndex).to.equal(0);
+        expect(offsetMetadata.highestLevelIndex).to.equal(0);
+      });
+
+      it("does not hold the nft at the highest level of the previous version", async () => {
+        await client.updateNft(
+          mint,
+          mintAuthority,
+          user.publicKey,
+          level2Offset,
+          principal
+        );
+
+        const offsetMetadata = await getOffsetMetadata();
+        expect(offsetMetadata.currentLevelIndex).to.equal(0);
+        expect(offsetMetadata.highestLevelIndex).to.equal(0);
+      });
+    });
+  }
 });
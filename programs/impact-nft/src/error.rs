@@ -14,4 +14,72 @@ pub enum ErrorCode {
     InvalidUpdateForMint,
     #[msg("Invalid fee recipient account")]
     InvalidFeeRecipient,
+    #[msg("Missing or invalid fee payer token account")]
+    InvalidFeePayerTokenAccount,
+    #[msg("Fee calculation overflowed")]
+    FeeCalculationOverflow,
+    #[msg("Invalid fee config")]
+    InvalidFeeConfig,
+    #[msg("Missing or invalid fee mint")]
+    InvalidFeeMint,
+    #[msg("Missing or invalid fee vault")]
+    InvalidFeeVault,
+    #[msg("Invalid fee distribution")]
+    InvalidFeeDistribution,
+    #[msg("No fee distribution is configured")]
+    NoFeeDistribution,
+    #[msg("Invalid fee waiver")]
+    InvalidFeeWaiver,
+    #[msg("Percentage fees cannot exceed 10,000 basis points")]
+    InvalidFeeBasisPoints,
+    #[msg("Fee exceeds the maximum accepted by the payer")]
+    FeeExceedsMaximum,
+    #[msg("The first level must start at offset 0")]
+    InvalidBaseOffset,
+    #[msg("Level offsets must be strictly increasing")]
+    UnorderedOffsetTiers,
+    #[msg("Level uri exceeds 200 characters")]
+    LevelUriTooLong,
+    #[msg("Level name exceeds 32 characters")]
+    LevelNameTooLong,
+    #[msg("Level symbol exceeds 10 characters")]
+    LevelSymbolTooLong,
+    #[msg("Level index out of range")]
+    InvalidLevelIndex,
+    #[msg("Level is in use by existing NFTs")]
+    LevelInUse,
+    #[msg("Missing collection accounts for a level")]
+    MissingCollectionAccounts,
+    #[msg("Collection mint is not a sized collection with a master edition")]
+    InvalidCollection,
+    #[msg("Token authority cannot verify items in the collection")]
+    InvalidCollectionAuthority,
+    #[msg("NFT is already resolved against the latest offset tiers")]
+    NftAlreadyMigrated,
+    #[msg("Downgrading NFTs is not allowed")]
+    DowngradeForbidden,
+    #[msg("Expanded metadata uri exceeds 200 characters")]
+    MetadataUriTooLong,
+    #[msg("The metadata uri requires the owner of the NFT")]
+    MissingUriOwner,
+    #[msg("Invalid owner token account")]
+    InvalidOwnerTokenAccount,
+    #[msg("Royalties cannot exceed 10,000 basis points")]
+    InvalidRoyalty,
+    #[msg("A level can list at most 4 creators")]
+    TooManyCreators,
+    #[msg("Level creators must be unique")]
+    DuplicateCreator,
+    #[msg("Level creator shares must sum to 100")]
+    InvalidCreatorShares,
+    #[msg("Account is not a legacy account of this program")]
+    NotALegacyAccount,
+    #[msg("The first level must not require a minimum principal")]
+    InvalidBaseMinPrincipal,
+    #[msg("Invalid metadata account for mint")]
+    InvalidMetadata,
+    #[msg("Fees are still charged, the fee distribution cannot be removed")]
+    FeeDistributionInUse,
+    #[msg("Levels cannot be edited until every legacy NFT is upgraded")]
+    LegacyUpgradesPending,
 }
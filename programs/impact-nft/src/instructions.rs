@@ -1,15 +1,47 @@
+mod add_fee_waiver;
 mod add_level;
+mod batch_update_nfts;
+mod burn_nft;
+mod create_fee_vault;
 mod create_global_state;
 mod create_nft;
 mod create_offset_tiers;
+mod create_offset_tiers_version;
+mod insert_level;
+mod migrate_nft;
+mod refresh_metadata;
+mod refresh_metadata_batch;
+mod remove_fee_waiver;
+mod remove_level;
 mod update_global_state;
+mod update_level;
 mod update_nft;
 mod update_offset_tiers;
+mod upgrade_global_state;
+mod upgrade_offset_metadata;
+mod upgrade_offset_tiers;
+mod withdraw_fees;
 
+pub use add_fee_waiver::*;
 pub use add_level::*;
+pub use batch_update_nfts::*;
+pub use burn_nft::*;
+pub use create_fee_vault::*;
 pub use create_global_state::*;
 pub use create_nft::*;
 pub use create_offset_tiers::*;
+pub use create_offset_tiers_version::*;
+pub use insert_level::*;
+pub use migrate_nft::*;
+pub use refresh_metadata::*;
+pub use refresh_metadata_batch::*;
+pub use remove_fee_waiver::*;
+pub use remove_level::*;
 pub use update_global_state::*;
+pub use update_level::*;
 pub use update_nft::*;
 pub use update_offset_tiers::*;
+pub use upgrade_global_state::*;
+pub use upgrade_offset_metadata::*;
+pub use upgrade_offset_tiers::*;
+pub use withdraw_fees::*;
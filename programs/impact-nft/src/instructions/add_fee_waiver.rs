@@ -0,0 +1,37 @@
+use crate::error::ErrorCode;
+use crate::seeds::FEE_WAIVER_SEED;
+use crate::state::{FeeWaiver, FeeWaiverInput, GlobalState};
+use anchor_lang::prelude::*;
+
+/// Permissioned. Requires the admin_update_authority.
+/// Waives or discounts fees for a wallet or a calling program
+#[derive(Accounts)]
+#[instruction(input: FeeWaiverInput)]
+pub struct AddFeeWaiver<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub admin_update_authority: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        seeds = [FEE_WAIVER_SEED, global_state.key().as_ref(), input.key.as_ref()],
+        bump,
+        payer = payer,
+        space = FeeWaiver::SPACE,
+    )]
+    pub fee_waiver: Account<'info, FeeWaiver>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn add_fee_waiver_handler(ctx: Context<AddFeeWaiver>, input: FeeWaiverInput) -> Result<()> {
+    require_gte!(10_000, input.discount, ErrorCode::InvalidFeeWaiver);
+
+    let global_state = ctx.accounts.global_state.key();
+    ctx.accounts
+        .fee_waiver
+        .set(global_state, input, ctx.bumps.fee_waiver);
+    Ok(())
+}
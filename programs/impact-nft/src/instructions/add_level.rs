@@ -1,38 +1,70 @@
 use crate::error::ErrorCode;
-use crate::seeds::OFFSET_TIERS_SEED;
-use crate::state::{GlobalState, Level, OffsetTiers};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, Level, LevelCounts, OffsetTiers};
+use crate::utils::tiers::{validate_levels, verify_collections};
 use anchor_lang::prelude::*;
 
+/// Permissioned. Requires the admin_update_authority.
+/// The payer covers rent for the space needed by the new levels
 #[derive(Accounts)]
+#[instruction(incoming: Vec<Level>)]
 pub struct AddLevels<'info> {
     pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
     #[account(
         has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: SystemAccount<'info>,
     #[account(
         mut,
-        seeds = [OFFSET_TIERS_SEED, global_state.key().as_ref()],
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
         bump,
+        realloc = OffsetTiers::space(offset_tiers.levels.len() + incoming.len()),
+        realloc::payer = payer,
+        realloc::zero = false,
     )]
     pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        realloc = LevelCounts::space(offset_tiers.levels.len() + incoming.len()),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    pub system_program: Program<'info, System>,
 }
 
 pub fn add_level_handler(ctx: Context<AddLevels>, incoming: Vec<Level>) -> Result<()> {
-    let current = &mut ctx.accounts.offset_tiers.levels;
-    let initial_length = current.len();
+    let offset_tiers = &mut ctx.accounts.offset_tiers;
+    let initial_length = offset_tiers.levels.len();
 
     msg!("{} incoming levels", incoming.len());
+    verify_collections(
+        &incoming,
+        &offset_tiers.levels,
+        ctx.accounts.token_authority.key,
+        ctx.remaining_accounts,
+    )?;
 
-    for level in incoming {
-        if current.len() == OffsetTiers::MAX_LEVELS {
-            break;
-        }
-        current.push(level);
-    }
+    offset_tiers.levels.extend(incoming);
+    validate_levels(&offset_tiers.levels, &ctx.accounts.global_state.fee_distribution)?;
+    let length = offset_tiers.levels.len();
+    ctx.accounts.level_counts.resize(length);
     msg!(
         "Added {} new levels to offsetTiers",
-        current.len() - initial_length
+        length - initial_length
     );
 
     Ok(())
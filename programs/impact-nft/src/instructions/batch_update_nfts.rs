@@ -0,0 +1,240 @@
+use crate::error::ErrorCode;
+use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{FeeVault, FeeWaiver, GlobalState, LevelCounts, NftUpdateInput, OffsetTiers};
+use crate::utils::fee::FeeAccounts;
+use crate::utils::metaplex::{unverify_nft, update_metadata, verify_nft, UriParams};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::compute_units::sol_remaining_compute_units;
+use anchor_lang::solana_program::sysvar;
+use anchor_spl::token::Token;
+use anchor_spl::token_interface::{self, TokenAccount, TokenInterface};
+
+use super::refresh_metadata_batch::{load_offset_metadata, load_owner};
+use super::update_nft::{calculate_new_level_index, charge_update_fee};
+
+/// The accounts expected in the remaining accounts for each nft, in the order of the updates:
+/// its offset metadata, mint, metadata and owner token account,
+/// then the mint, metadata and master edition of its current collection and of its new collection.
+/// The owner token account is only read if the new level's uri contains the {owner} placeholder,
+/// the program id can be passed in its place otherwise
+pub const UPDATE_ACCOUNTS: usize = 10;
+
+/// The compute units kept in reserve for each update, until an update of the batch
+/// has cost more. The batch stops before an update once fewer units than the reserve remain.
+/// Level changes make three Metaplex CPIs, the cost of each update is logged
+pub const MIN_COMPUTE_UNITS_PER_UPDATE: u64 = 100_000;
+
+/// Permissionless. Requires the external admin_mint_authority.
+/// The batch form of update_nft, for nfts resolved against the same version of the offset tiers
+#[derive(Accounts)]
+pub struct BatchUpdateNfts<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    // needed for verify ix
+    pub admin_mint_authority: Signer<'info>,
+    /// CHECK: Verified with function
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: UncheckedAccount<'info>,
+
+    #[account(
+        has_one = admin_mint_authority @ ErrorCode::InvalidMintAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(offset_tiers.version).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MplTokenMetadata>,
+    pub system_program: Program<'info, System>,
+
+    // only required if an update fee is configured
+    #[account(
+        mut,
+        has_one = global_state @ ErrorCode::InvalidFeeVault,
+    )]
+    pub fee_vault: Option<Box<Account<'info, FeeVault>>>,
+    // only required for SPL fees
+    #[account(mut)]
+    pub fee_vault_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut)]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub fee_mint: Option<Box<InterfaceAccount<'info, token_interface::Mint>>>,
+    pub fee_token_program: Option<Interface<'info, TokenInterface>>,
+    // waives or discounts the fee for the payer or the calling program
+    #[account(
+        has_one = global_state @ ErrorCode::InvalidFeeWaiver,
+    )]
+    pub fee_waiver: Option<Box<Account<'info, FeeWaiver>>>,
+    /// CHECK: The instructions sysvar, only required for fee waivers keyed by the calling program
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+}
+
+/// Applies the updates in order for as long as the compute budget allows.
+/// max_fee_payable applies to each nft. Returns the number of nfts updated,
+/// the remaining updates can be sent again in a new transaction
+pub fn batch_update_nfts_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, BatchUpdateNfts<'info>>,
+    updates: Vec<NftUpdateInput>,
+    max_fee_payable: u64,
+) -> Result<u16> {
+    let global_state_key = ctx.accounts.global_state.key();
+    let token_authority_bump = ctx.bumps.token_authority;
+
+    if ctx.accounts.offset_tiers.levels.is_empty() {
+        return Err(ErrorCode::NoOffsetTiers.into());
+    }
+    require!(
+        !updates.is_empty() && ctx.remaining_accounts.len() == updates.len() * UPDATE_ACCOUNTS,
+        ErrorCode::InvalidOffsetMetadata
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let mut updated: u16 = 0;
+    // grows to the most expensive update of the batch
+    let mut reserved_compute_units = MIN_COMPUTE_UNITS_PER_UPDATE;
+    for (update, accounts) in updates
+        .iter()
+        .zip(ctx.remaining_accounts.chunks_exact(UPDATE_ACCOUNTS))
+    {
+        let remaining_compute_units = sol_remaining_compute_units();
+        // always make progress, so that a crank can't get stuck on a batch that is too large
+        if updated > 0 && remaining_compute_units < reserved_compute_units {
+            msg!("Compute budget exhausted after {} updates", updated);
+            break;
+        }
+
+        let [
+            offset_metadata_info,
+            mint,
+            metadata,
+            owner_token_account,
+            collection_mint,
+            collection_metadata,
+            collection_master_edition,
+            new_collection_mint,
+            new_collection_metadata,
+            new_collection_master_edition,
+        ] = accounts
+        else {
+            unreachable!()
+        };
+
+        let mut offset_metadata = load_offset_metadata(
+            offset_metadata_info,
+            mint,
+            metadata,
+            &global_state_key,
+            ctx.accounts.offset_tiers.version,
+        )?;
+        let new_level_index = calculate_new_level_index(
+            &ctx.accounts.global_state,
+            &offset_metadata,
+            &ctx.accounts.offset_tiers,
+            update.offset_amount,
+            update.principal,
+            now,
+        )?;
+        let current_level_index = offset_metadata.current_level_index as usize;
+        let levels = &ctx.accounts.offset_tiers.levels;
+        require_keys_eq!(*collection_mint.key, levels[current_level_index].collection_mint);
+        require_keys_eq!(*new_collection_mint.key, levels[new_level_index].collection_mint);
+
+        // the offset is recorded on every update, the metadata only changes with the level
+        offset_metadata.record_update(update.offset_amount, update.principal, now);
+        if new_level_index != current_level_index {
+            require!(
+                **mint.try_borrow_lamports()? > 0,
+                ErrorCode::InvalidUpdateForMint
+            );
+            let new_level = &ctx.accounts.offset_tiers.levels[new_level_index];
+            charge_update_fee(
+                &ctx.accounts.global_state,
+                new_level,
+                current_level_index,
+                new_level_index,
+                update.principal,
+                ctx.accounts.fee_waiver.as_deref(),
+                ctx.accounts.instructions_sysvar.as_deref(),
+                FeeAccounts {
+                    fee_payer: &ctx.accounts.payer,
+                    fee_vault: ctx.accounts.fee_vault.as_deref_mut(),
+                    fee_vault_token_account: ctx.accounts.fee_vault_token_account.as_deref(),
+                    fee_token_program: ctx.accounts.fee_token_program.as_ref(),
+                    fee_mint: ctx.accounts.fee_mint.as_deref(),
+                    fee_payer_token_account: ctx.accounts.payer_token_account.as_deref(),
+                },
+                max_fee_payable,
+            )?;
+
+            offset_metadata.set_level_reached_at(now);
+            offset_metadata.set_level_index(new_level_index);
+
+            msg!("Updating {} to level {}", mint.key, new_level_index);
+            unverify_nft(
+                metadata.clone(),
+                ctx.accounts.payer.to_account_info(),
+                collection_mint.clone(),
+                collection_metadata.clone(),
+                collection_master_edition.clone(),
+                &global_state_key,
+                ctx.accounts.token_authority.to_account_info(),
+                token_authority_bump,
+                ctx.accounts.token_metadata_program.to_account_info(),
+            )?;
+            update_metadata(
+                new_level,
+                &UriParams {
+                    offset: update.offset_amount,
+                    level_index: new_level_index,
+                    mint: mint.key(),
+                    owner: load_owner(owner_token_account, mint.key)?,
+                },
+                metadata.clone(),
+                &global_state_key,
+                ctx.accounts.token_authority.to_account_info(),
+                token_authority_bump,
+                ctx.accounts.token_metadata_program.to_account_info(),
+            )?;
+            verify_nft(
+                metadata.clone(),
+                ctx.accounts.payer.to_account_info(),
+                new_collection_mint.clone(),
+                new_collection_metadata.clone(),
+                new_collection_master_edition.clone(),
+                &global_state_key,
+                ctx.accounts.token_authority.to_account_info(),
+                token_authority_bump,
+                ctx.accounts.token_metadata_program.to_account_info(),
+            )?;
+            ctx.accounts
+                .level_counts
+                .record_level_change(Some(current_level_index), new_level_index);
+        }
+        offset_metadata.exit(ctx.program_id)?;
+        updated += 1;
+
+        let update_cost = remaining_compute_units.saturating_sub(sol_remaining_compute_units());
+        msg!("Update used {} compute units", update_cost);
+        reserved_compute_units = reserved_compute_units.max(update_cost);
+    }
+
+    Ok(updated)
+}
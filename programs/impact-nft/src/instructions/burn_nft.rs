@@ -0,0 +1,115 @@
+use crate::error::ErrorCode;
+use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetMetadata, OffsetTiers};
+use crate::utils::metaplex::{burn_nft, check_metadata_account, unverify_nft};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+/// Requires the owner of the nft. Retires the nft, closing its
+/// offset metadata to the rent recipient
+#[derive(Accounts)]
+pub struct BurnNft<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    /// CHECK: Any account chosen by the owner to receive the offset metadata rent
+    #[account(mut)]
+    pub rent_recipient: UncheckedAccount<'info>,
+    /// CHECK: Verified with function
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: UncheckedAccount<'info>,
+
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(offset_metadata.tiers_version).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    #[account(
+        mut,
+        seeds = [OFFSET_METADATA_SEED, mint.key().as_ref(), global_state.key().as_ref()],
+        bump,
+        close = rent_recipient,
+    )]
+    pub offset_metadata: Account<'info, OffsetMetadata>,
+
+    #[account(mut)]
+    pub mint: Account<'info, Mint>,
+    /// CHECK: Verified with the check_metadata_account helper function
+    #[account(
+        mut,
+        constraint = check_metadata_account(&metadata, &mint.to_account_info()).is_ok()
+            @ ErrorCode::InvalidMetadata,
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    #[account(mut)]
+    /// CHECK: Checked by CPI to Metaplex
+    pub master_edition: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner,
+        constraint = owner_token_account.amount == 1 @ ErrorCode::InvalidOwnerTokenAccount,
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Checked by instruction
+    pub collection_mint: UncheckedAccount<'info>,
+    #[account(mut)]
+    /// CHECK: Checked by CPI to Metaplex
+    pub collection_metadata: UncheckedAccount<'info>,
+    /// CHECK: Checked by CPI to Metaplex
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, MplTokenMetadata>,
+}
+
+pub fn burn_nft_handler(ctx: Context<BurnNft>) -> Result<()> {
+    let level_index = ctx.accounts.offset_metadata.current_level_index as usize;
+    require_keys_eq!(
+        *ctx.accounts.collection_mint.key,
+        ctx.accounts.offset_tiers.levels[level_index].collection_mint
+    );
+
+    // unverifying also decrements the size of the level's collection
+    msg!("Unverifying...");
+    unverify_nft(
+        ctx.accounts.metadata.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.collection_mint.to_account_info(),
+        ctx.accounts.collection_metadata.to_account_info(),
+        ctx.accounts.collection_master_edition.to_account_info(),
+        &ctx.accounts.global_state.key(),
+        ctx.accounts.token_authority.to_account_info(),
+        ctx.bumps.token_authority,
+        ctx.accounts.token_metadata_program.to_account_info(),
+    )?;
+    msg!("Burning...");
+    burn_nft(
+        ctx.accounts.metadata.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.owner_token_account.to_account_info(),
+        ctx.accounts.master_edition.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.token_metadata_program.to_account_info(),
+    )?;
+
+    ctx.accounts.level_counts.remove_nft(level_index);
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use crate::error::ErrorCode;
+use crate::seeds::FEE_VAULT_SEED;
+use crate::state::{FeeVault, GlobalState};
+use crate::utils::token::create_fee_vault_token_account;
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenInterface};
+
+/// Permissioned. Requires the admin_update_authority.
+/// Creates the vault that collects SOL fees or, if a fee mint
+/// is passed, the vault that collects fees in that token
+#[derive(Accounts)]
+pub struct CreateFeeVault<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub admin_update_authority: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    pub fee_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [
+            FEE_VAULT_SEED,
+            global_state.key().as_ref(),
+            FeeVault::mint_seed(fee_mint.as_ref().map(|m| m.key())).as_ref(),
+        ],
+        bump,
+        payer = payer,
+        space = FeeVault::SPACE,
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+    #[account(mut)]
+    /// CHECK: Initialized in instruction handler as the fee vault's associated token account
+    pub fee_vault_token_account: Option<UncheckedAccount<'info>>,
+    pub fee_token_program: Option<Interface<'info, TokenInterface>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn create_fee_vault_handler(ctx: Context<CreateFeeVault>) -> Result<()> {
+    let fee_mint = &ctx.accounts.fee_mint;
+
+    if let Some(fee_mint) = fee_mint {
+        create_fee_vault_token_account(
+            ctx.accounts
+                .associated_token_program
+                .as_ref()
+                .ok_or(ErrorCode::InvalidFeeVault)?,
+            &ctx.accounts.payer,
+            ctx.accounts
+                .fee_vault_token_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidFeeVault)?,
+            &ctx.accounts.fee_vault.to_account_info(),
+            &fee_mint.to_account_info(),
+            &ctx.accounts.system_program,
+            ctx.accounts
+                .fee_token_program
+                .as_ref()
+                .ok_or(ErrorCode::InvalidFeeMint)?,
+        )?;
+    }
+
+    ctx.accounts.fee_vault.set(
+        ctx.accounts.global_state.key(),
+        fee_mint.as_ref().map(|m| m.key()),
+        ctx.bumps.fee_vault,
+    );
+    Ok(())
+}
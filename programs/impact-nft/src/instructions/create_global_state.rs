@@ -1,5 +1,6 @@
 use crate::seeds::TOKEN_AUTHORITY_SEED;
 use crate::state::{GlobalState, GlobalStateCreateInput};
+use crate::utils::fee::validate_fees;
 use anchor_lang::prelude::*;
 
 #[derive(Accounts, Clone)]
@@ -30,12 +31,18 @@ pub fn create_global_state_handler(
     ctx: Context<CreateGlobalState>,
     state: GlobalStateCreateInput,
 ) -> Result<()> {
+    validate_fees(&[&state.fee, &state.update_fee], &state.fee_distribution)?;
+
     let global_state = &mut ctx.accounts.global_state;
     global_state.set(
         ctx.accounts.admin_update_authority.key(),
         state.admin_mint_authority,
         state.levels,
         state.fee,
+        state.fee_distribution,
+        state.update_fee,
+        state.update_fee_scaling,
+        state.downgrade_policy,
     );
     Ok(())
 }
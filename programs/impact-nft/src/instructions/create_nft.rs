@@ -1,12 +1,14 @@
 use crate::error::ErrorCode;
-use crate::seeds::{OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
-use crate::state::{GlobalState, OffsetMetadata, OffsetTiers};
-// use crate::utils::fee::handle_fees;
-use crate::utils::metaplex::{create_master_edition_account, create_metadata_account, verify_nft};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{FeeVault, FeeWaiver, GlobalState, LevelCounts, OffsetMetadata, OffsetTiers};
+use crate::utils::fee::{apply_discount, calculate_fee, get_fee_discount, handle_fees, FeeAccounts};
+use crate::utils::metaplex::{create_master_edition_account, create_metadata_account, verify_nft, UriParams};
 use crate::utils::token::{create_mint, create_token_account, mint_to};
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar;
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::Token;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
 
 /// Permissionless. The required external verification
@@ -24,17 +26,23 @@ pub struct MintNft<'info> {
 
     #[account(
         has_one = admin_mint_authority @ ErrorCode::InvalidMintAuthority,
-    // RE-ENABLE ONCE FEES ARE SUPPORTED
-        // constraint =  global_state.fee.as_ref().and_then(|fee_config| fee_config.spl_token_mint) == payer_token_account.as_ref().map(|payer_token_account| payer_token_account.mint)
-    // the fee config recipient must be either the recipient account or the recipient token account, depending on the coin type
-    // checked in code as the constraint is too complex to put here
     )]
     pub global_state: Box<Account<'info, GlobalState>>,
     #[account(
-        seeds = [OFFSET_TIERS_SEED, global_state.key().as_ref()],
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
         bump,
     )]
     pub offset_tiers: Box<Account<'info, OffsetTiers>>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Box<Account<'info, LevelCounts>>,
     #[account(
         init,
         seeds = [OFFSET_METADATA_SEED, mint.key().as_ref(), global_state.key().as_ref()],
@@ -60,8 +68,7 @@ pub struct MintNft<'info> {
     /// CHECK: The owner of the token account the nft is minted to
     pub mint_nft_to_owner: UncheckedAccount<'info>,
 
-    #[account(address = offset_tiers.levels[0].collection_mint)]
-    /// CHECK: Checked by offsetTiers state
+    /// CHECK: Checked against the level resolved from the offset
     pub collection_mint: UncheckedAccount<'info>,
     /// CHECK: Checked by CPI to Metaplex
     #[account(mut)]
@@ -76,18 +83,35 @@ pub struct MintNft<'info> {
     pub token_metadata_program: Program<'info, MplTokenMetadata>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
-    // disable fee payment until the client integration supports it
-    // #[account(mut)]
-    // pub payer_token_account: Option<Box<Account<'info, TokenAccount>>>,
-    // /// CHECK, can be anything as long as it matches the recipient in the fee config
-    // #[account(mut)]
-    // pub recipient: Option<UncheckedAccount<'info>>,
-    // #[account(mut)]
-    // pub recipient_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    // only required if a fee is configured
+    #[account(
+        mut,
+        has_one = global_state @ ErrorCode::InvalidFeeVault,
+    )]
+    pub fee_vault: Option<Box<Account<'info, FeeVault>>>,
+    // only required for SPL fees
+    #[account(mut)]
+    pub fee_vault_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut)]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub fee_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    pub fee_token_program: Option<Interface<'info, TokenInterface>>,
+    // waives or discounts the fee for the payer or the calling program
+    #[account(
+        has_one = global_state @ ErrorCode::InvalidFeeWaiver,
+    )]
+    pub fee_waiver: Option<Box<Account<'info, FeeWaiver>>>,
+    /// CHECK: The instructions sysvar, only required for fee waivers keyed by the calling program
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
 }
 
-/** TODO: add offset update logic */
-pub fn mint_nft_handler(ctx: Context<MintNft>, offset_amount: u64, _principal: u64) -> Result<()> {
+pub fn mint_nft_handler(
+    ctx: Context<MintNft>,
+    offset_amount: u64,
+    principal: u64,
+    max_fee_payable: u64,
+) -> Result<()> {
     let mint = &ctx.accounts.mint;
     let token_authority = &ctx.accounts.token_authority;
     let payer = &ctx.accounts.payer;
@@ -95,7 +119,7 @@ pub fn mint_nft_handler(ctx: Context<MintNft>, offset_amount: u64, _principal: u
     let token_program = &ctx.accounts.token_program;
     let token_metadata_program = &ctx.accounts.token_metadata_program;
     let rent = &ctx.accounts.rent;
-    let offset_tiers = &mut ctx.accounts.offset_tiers;
+    let offset_tiers = &ctx.accounts.offset_tiers;
     let metadata = &mut ctx.accounts.metadata;
     let master_edition = &mut ctx.accounts.master_edition;
     let global_state = &ctx.accounts.global_state;
@@ -105,33 +129,15 @@ pub fn mint_nft_handler(ctx: Context<MintNft>, offset_amount: u64, _principal: u
         return Err(ErrorCode::NoOffsetTiers.into());
     }
 
-    // ensure the fee recipient matches the fee config
-    // match &ctx.accounts.global_state.fee {
-    //     Some(fee_config) => match fee_config.coin_type {
-    //         CoinType::Spl => {
-    //             require_keys_eq!(
-    //                 fee_config.recipient,
-    //                 ctx.accounts.recipient_token_account.as_ref().unwrap().key()
-    //             );
-    //         }
-    //         CoinType::Native => {
-    //             require_keys_eq!(
-    //                 fee_config.recipient,
-    //                 ctx.accounts.recipient.as_ref().unwrap().key()
-    //             );
-    //         }
-    //     },
-    //     None => {
-    //         require!(
-    //             ctx.accounts.recipient_token_account.is_none(),
-    //             ErrorCode::InvalidFeeRecipient
-    //         );
-    //         require!(
-    //             ctx.accounts.recipient.is_none(),
-    //             ErrorCode::InvalidFeeRecipient
-    //         );
-    //     }
-    // }
+    // mint straight into the level of the initial offset, as far as it can be reached
+    // without time spent at the levels below
+    let offset_level_index = offset_tiers
+        .get_index(offset_amount, principal)
+        .unwrap_or(0);
+    let level_index = offset_tiers.cap_promotion(0, offset_level_index, 0);
+    let level = &offset_tiers.levels[level_index];
+    require_keys_eq!(*ctx.accounts.collection_mint.key, level.collection_mint);
+    msg!("Minting into level {}", level_index);
 
     msg!("creating mint");
     create_mint(
@@ -164,7 +170,13 @@ pub fn mint_nft_handler(ctx: Context<MintNft>, offset_amount: u64, _principal: u
 
     msg!("creating metadata account");
     create_metadata_account(
-        &offset_tiers.levels[0],
+        level,
+        &UriParams {
+            offset: offset_amount,
+            level_index,
+            mint: mint.key(),
+            owner: Some(ctx.accounts.mint_nft_to_owner.key()),
+        },
         metadata.to_account_info(),
         mint.to_account_info(),
         payer.to_account_info(),
@@ -191,36 +203,42 @@ pub fn mint_nft_handler(ctx: Context<MintNft>, offset_amount: u64, _principal: u
         rent.to_account_info(),
     )?;
 
-    if let Some(_fee_config) = &ctx.accounts.global_state.fee {
-        msg!("Fee config is set but fees are currently disabled");
-        // let recipient: AccountInfo = ctx.accounts.recipient.as_ref().map_or_else(
-        //     || {
-        //         ctx.accounts
-        //             .recipient_token_account
-        //             .as_ref()
-        //             .unwrap()
-        //             .to_account_info()
-        //     },
-        //     |a| a.to_account_info(),
-        // );
-        // let fee_payer_token_account = ctx
-        //     .accounts
-        //     .payer_token_account
-        //     .as_ref()
-        //     .map(|a| a.to_account_info());
-        // handle_fees(
-        //     token_program,
-        //     fee_config,
-        //     &ctx.accounts.payer,
-        //     fee_payer_token_account,
-        //     &recipient,
-        //     principal,
-        // )?;
+    let global_fee = global_state.fee_at(Clock::get()?.slot);
+    if let Some(fee_config) = level.fee_config(global_fee) {
+        let discount = get_fee_discount(
+            ctx.accounts.fee_waiver.as_deref(),
+            &payer.key(),
+            ctx.accounts.instructions_sysvar.as_deref(),
+        )?;
+        let amount = apply_discount(calculate_fee(fee_config, principal)?, discount)?;
+        handle_fees(
+            fee_config,
+            FeeAccounts {
+                fee_payer: payer,
+                fee_vault: ctx.accounts.fee_vault.as_deref_mut(),
+                fee_vault_token_account: ctx.accounts.fee_vault_token_account.as_deref(),
+                fee_token_program: ctx.accounts.fee_token_program.as_ref(),
+                fee_mint: ctx.accounts.fee_mint.as_deref(),
+                fee_payer_token_account: ctx.accounts.payer_token_account.as_deref(),
+            },
+            amount,
+            max_fee_payable,
+        )?;
     }
 
     ctx.accounts
         .offset_metadata
-        .set(offset_amount, ctx.bumps.offset_metadata, 0);
+        .set(
+            offset_amount,
+            ctx.bumps.offset_metadata,
+            level_index,
+            Clock::get()?.unix_timestamp,
+        );
+    ctx.accounts.offset_metadata.set_principal(principal);
+    ctx.accounts
+        .offset_metadata
+        .set_tiers_version(global_state.tiers_version);
+    ctx.accounts.level_counts.record_level_change(None, level_index);
 
     msg!("Verifying collection");
     verify_nft(
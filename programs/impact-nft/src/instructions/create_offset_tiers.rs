@@ -1,9 +1,12 @@
-use crate::seeds::OFFSET_TIERS_SEED;
-use crate::state::{GlobalState, OffsetTiers, OffsetTiersInput};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetTiers, OffsetTiersInput};
+use crate::utils::tiers::{validate_levels, verify_collections};
 use anchor_lang::prelude::*;
 
 /// Permissioned. The required external verification is
-/// the admin_update_authority
+/// the admin_update_authority.
+/// Creates the first version of the offset tiers,
+/// later versions are created with create_offset_tiers_version
 #[derive(Accounts, Clone)]
 #[instruction(state: OffsetTiersInput)]
 pub struct CreateOffsetTiers<'info> {
@@ -12,14 +15,31 @@ pub struct CreateOffsetTiers<'info> {
     pub payer: Signer<'info>,
     #[account( has_one = admin_update_authority )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: SystemAccount<'info>,
     #[account(
         init,
-        seeds = [OFFSET_TIERS_SEED, global_state.key().as_ref()],
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
         bump,
         payer = payer,
-        space = OffsetTiers::SPACE,
+        space = OffsetTiers::space(state.levels.len()),
     )]
     pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        init,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        payer = payer,
+        space = LevelCounts::space(state.levels.len()),
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
     pub system_program: Program<'info, System>,
 }
 
@@ -27,7 +47,18 @@ pub fn create_offset_tiers_handler(
     ctx: Context<CreateOffsetTiers>,
     state: OffsetTiersInput,
 ) -> Result<()> {
+    validate_levels(&state.levels, &ctx.accounts.global_state.fee_distribution)?;
+    verify_collections(
+        &state.levels,
+        &[],
+        ctx.accounts.token_authority.key,
+        ctx.remaining_accounts,
+    )?;
+    ctx.accounts
+        .level_counts
+        .set(state.levels.len(), ctx.bumps.level_counts);
     let offset_tiers = &mut ctx.accounts.offset_tiers;
     offset_tiers.set(state);
+    offset_tiers.version = ctx.accounts.global_state.tiers_version;
     Ok(())
 }
@@ -0,0 +1,83 @@
+use crate::error::ErrorCode;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetTiers, OffsetTiersInput};
+use crate::utils::tiers::{validate_levels, verify_collections};
+use anchor_lang::prelude::*;
+
+/// Permissioned. Requires the admin_update_authority.
+/// Publishes a new version of the offset tiers. New NFTs are minted against it,
+/// existing NFTs keep their version until they are migrated with migrate_nft
+#[derive(Accounts, Clone)]
+#[instruction(state: OffsetTiersInput)]
+pub struct CreateOffsetTiersVersion<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: SystemAccount<'info>,
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
+        bump,
+    )]
+    pub previous_offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        init,
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version + 1).as_ref(),
+        ],
+        bump,
+        payer = payer,
+        space = OffsetTiers::space(state.levels.len()),
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        init,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        payer = payer,
+        space = LevelCounts::space(state.levels.len()),
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn create_offset_tiers_version_handler(
+    ctx: Context<CreateOffsetTiersVersion>,
+    state: OffsetTiersInput,
+) -> Result<()> {
+    validate_levels(&state.levels, &ctx.accounts.global_state.fee_distribution)?;
+    // collections carried over from the previous version have already been checked
+    verify_collections(
+        &state.levels,
+        &ctx.accounts.previous_offset_tiers.levels,
+        ctx.accounts.token_authority.key,
+        ctx.remaining_accounts,
+    )?;
+
+    let global_state = &mut ctx.accounts.global_state;
+    global_state.tiers_version += 1;
+
+    ctx.accounts
+        .level_counts
+        .set(state.levels.len(), ctx.bumps.level_counts);
+    let offset_tiers = &mut ctx.accounts.offset_tiers;
+    offset_tiers.set(state);
+    offset_tiers.version = global_state.tiers_version;
+
+    msg!("Created offset tiers version {}", offset_tiers.version);
+    Ok(())
+}
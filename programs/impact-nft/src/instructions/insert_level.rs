@@ -0,0 +1,79 @@
+use crate::error::ErrorCode;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, Level, LevelCounts, OffsetTiers};
+use crate::utils::tiers::{validate_levels, verify_collections};
+use anchor_lang::prelude::*;
+
+/// Permissioned. Requires the admin_update_authority.
+/// The payer covers rent for the space needed by the new level
+#[derive(Accounts)]
+pub struct InsertLevel<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
+        bump,
+        realloc = OffsetTiers::space(offset_tiers.levels.len() + 1),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        realloc = LevelCounts::space(offset_tiers.levels.len() + 1),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn insert_level_handler(ctx: Context<InsertLevel>, index: u16, level: Level) -> Result<()> {
+    let offset_tiers = &mut ctx.accounts.offset_tiers;
+    let level_counts = &mut ctx.accounts.level_counts;
+    let index = index as usize;
+    // legacy NFTs refer to their level by an index that is not counted until they are upgraded
+    require_eq!(
+        level_counts.pending_legacy_upgrades,
+        0,
+        ErrorCode::LegacyUpgradesPending
+    );
+
+    require_gte!(
+        offset_tiers.levels.len(),
+        index,
+        ErrorCode::InvalidLevelIndex
+    );
+    // the levels after the insertion point are shifted up
+    require_eq!(level_counts.nfts_from(index), 0, ErrorCode::LevelInUse);
+
+    verify_collections(
+        std::slice::from_ref(&level),
+        &offset_tiers.levels,
+        ctx.accounts.token_authority.key,
+        ctx.remaining_accounts,
+    )?;
+    offset_tiers.levels.insert(index, level);
+    level_counts.counts.insert(index, 0);
+    validate_levels(&offset_tiers.levels, &ctx.accounts.global_state.fee_distribution)?;
+
+    msg!("Inserted level at index {}", index);
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use crate::error::ErrorCode;
+use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetMetadata, OffsetTiers};
+use crate::utils::metaplex::{check_metadata_account, unverify_nft, update_metadata, verify_nft, UriParams};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+/// Permissionless. Re-resolves an NFT's level against the latest
+/// version of the offset tiers, using its recorded offset and principal.
+/// Level indices of different versions do not refer to the same levels, so the
+/// NFT is placed by its thresholds in the new version alone: the downgrade policy
+/// and promotion durations do not apply, and its highest level starts over
+#[derive(Accounts)]
+pub struct MigrateNft<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Verified with function
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: UncheckedAccount<'info>,
+
+    pub global_state: Account<'info, GlobalState>,
+    // the version the nft was resolved against
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(offset_metadata.tiers_version).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Box<Account<'info, OffsetTiers>>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Box<Account<'info, LevelCounts>>,
+    // the latest version
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
+        bump,
+    )]
+    pub new_offset_tiers: Box<Account<'info, OffsetTiers>>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, new_offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub new_level_counts: Box<Account<'info, LevelCounts>>,
+    #[account(
+        mut,
+        seeds = [OFFSET_METADATA_SEED, mint.key().as_ref(), global_state.key().as_ref()],
+        bump,
+    )]
+    pub offset_metadata: Account<'info, OffsetMetadata>,
+
+    pub mint: Account<'info, Mint>,
+    /// CHECK: Verified with the check_metadata_account helper function
+    #[account(
+        mut,
+        constraint = check_metadata_account(&metadata, &mint.to_account_info()).is_ok()
+            @ ErrorCode::InvalidMetadata,
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    // only required if the level's uri contains the {owner} placeholder
+    #[account(
+        token::mint = mint,
+        constraint = owner_token_account.amount == 1 @ ErrorCode::InvalidOwnerTokenAccount,
+    )]
+    pub owner_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Checked by instruction
+    pub new_collection_mint: UncheckedAccount<'info>,
+    #[account(mut)]
+    /// CHECK: Checked by CPI to Metaplex
+    pub new_collection_metadata: UncheckedAccount<'info>,
+    /// CHECK: Checked by CPI to Metaplex
+    pub new_collection_master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by instruction
+    pub collection_mint: UncheckedAccount<'info>,
+    #[account(mut)]
+    /// CHECK: Checked by CPI to Metaplex
+    pub collection_metadata: UncheckedAccount<'info>,
+    /// CHECK: Checked by CPI to Metaplex
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    pub token_metadata_program: Program<'info, MplTokenMetadata>,
+}
+
+pub fn migrate_nft_handler(ctx: Context<MigrateNft>) -> Result<()> {
+    let global_state = &ctx.accounts.global_state;
+    let offset_metadata = &mut ctx.accounts.offset_metadata;
+    let offset_tiers = &ctx.accounts.offset_tiers;
+    let new_offset_tiers = &ctx.accounts.new_offset_tiers;
+    let token_authority_bump = ctx.bumps.token_authority;
+
+    require_neq!(
+        offset_metadata.tiers_version,
+        global_state.tiers_version,
+        ErrorCode::NftAlreadyMigrated
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let current_level_index = offset_metadata.current_level_index as usize;
+    let new_level_index = new_offset_tiers
+        .get_index(offset_metadata.offset, offset_metadata.principal)
+        .unwrap_or(0);
+    require_keys_eq!(
+        *ctx.accounts.collection_mint.key,
+        offset_tiers.levels[current_level_index].collection_mint
+    );
+    require_keys_eq!(
+        *ctx.accounts.new_collection_mint.key,
+        new_offset_tiers.levels[new_level_index].collection_mint
+    );
+
+    msg!(
+        "Migrating from level {} of version {} to level {} of version {}",
+        current_level_index,
+        offset_metadata.tiers_version,
+        new_level_index,
+        global_state.tiers_version
+    );
+
+    msg!("Unverifying...");
+    unverify_nft(
+        ctx.accounts.metadata.to_account_info(),
+        ctx.accounts.payer.to_account_info(),
+        ctx.accounts.collection_mint.to_account_info(),
+        ctx.accounts.collection_metadata.to_account_info(),
+        ctx.accounts.collection_master_edition.to_account_info(),
+        &global_state.key(),
+        ctx.accounts.token_authority.to_account_info(),
+        token_authority_bump,
+        ctx.accounts.token_metadata_program.to_account_info(),
+    )?;
+    msg!("Updating...");
+    update_metadata(
+        &new_offset_tiers.levels[new_level_index],
+        &UriParams {
+            offset: offset_metadata.offset,
+            level_index: new_level_index,
+            mint: ctx.accounts.mint.key(),
+            owner: ctx.accounts.owner_token_account.as_ref().map(|account| account.owner),
+        },
+        ctx.accounts.metadata.to_account_info(),
+        &global_state.key(),
+        ctx.accounts.token_authority.to_account_info(),
+        token_authority_bump,
+        ctx.accounts.token_metadata_program.to_account_info(),
+    )?;
+    msg!("Verifying...");
+    verify_nft(
+        ctx.accounts.metadata.to_account_info(),
+        ctx.accounts.payer.to_account_info(),
+        ctx.accounts.new_collection_mint.to_account_info(),
+        ctx.accounts.new_collection_metadata.to_account_info(),
+        ctx.accounts.new_collection_master_edition.to_account_info(),
+        &global_state.key(),
+        ctx.accounts.token_authority.to_account_info(),
+        token_authority_bump,
+        ctx.accounts.token_metadata_program.to_account_info(),
+    )?;
+
+    ctx.accounts.level_counts.remove_nft(current_level_index);
+    ctx.accounts.new_level_counts.add_nft(new_level_index);
+    if new_level_index != current_level_index {
+        offset_metadata.set_level_reached_at(now);
+    }
+    offset_metadata.reset_level_index(new_level_index);
+    offset_metadata.set_tiers_version(global_state.tiers_version);
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use crate::error::ErrorCode;
+use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
+use crate::seeds::{offset_tiers_version_seed, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, OffsetMetadata, OffsetTiers};
+use crate::utils::metaplex::{check_metadata_account, update_metadata, UriParams};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+/// Permissionless. Rewrites an NFT's metadata from the current
+/// definition of its level, leaving its collection verification as is
+#[derive(Accounts)]
+pub struct RefreshMetadata<'info> {
+    /// CHECK: Verified with function
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: UncheckedAccount<'info>,
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(offset_metadata.tiers_version).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        seeds = [OFFSET_METADATA_SEED, mint.key().as_ref(), global_state.key().as_ref()],
+        bump,
+    )]
+    pub offset_metadata: Account<'info, OffsetMetadata>,
+    pub mint: Account<'info, Mint>,
+    /// CHECK: Verified with the check_metadata_account helper function
+    #[account(
+        mut,
+        constraint = check_metadata_account(&metadata, &mint.to_account_info()).is_ok()
+            @ ErrorCode::InvalidMetadata,
+    )]
+    pub metadata: UncheckedAccount<'info>,
+    // only required if the level's uri contains the {owner} placeholder
+    #[account(
+        token::mint = mint,
+        constraint = owner_token_account.amount == 1 @ ErrorCode::InvalidOwnerTokenAccount,
+    )]
+    pub owner_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_metadata_program: Program<'info, MplTokenMetadata>,
+}
+
+pub fn refresh_metadata_handler(ctx: Context<RefreshMetadata>) -> Result<()> {
+    let offset_metadata = &ctx.accounts.offset_metadata;
+    let level_index = offset_metadata.current_level_index as usize;
+
+    update_metadata(
+        &ctx.accounts.offset_tiers.levels[level_index],
+        &UriParams {
+            offset: offset_metadata.offset,
+            level_index,
+            mint: ctx.accounts.mint.key(),
+            owner: ctx
+                .accounts
+                .owner_token_account
+                .as_ref()
+                .map(|account| account.owner),
+        },
+        ctx.accounts.metadata.to_account_info(),
+        &ctx.accounts.global_state.key(),
+        ctx.accounts.token_authority.to_account_info(),
+        ctx.bumps.token_authority,
+        ctx.accounts.token_metadata_program.to_account_info(),
+    )
+}
@@ -0,0 +1,130 @@
+use crate::error::ErrorCode;
+use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
+use crate::seeds::{offset_tiers_version_seed, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, OffsetMetadata, OffsetTiers};
+use crate::utils::metaplex::{check_metadata_account, update_metadata, UriParams};
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+
+/// The accounts expected in the remaining accounts for each NFT:
+/// its offset metadata, mint, metadata and owner token account.
+/// The owner token account is only read if the level's uri contains the {owner} placeholder,
+/// the program id can be passed in its place otherwise
+pub const REFRESH_ACCOUNTS: usize = 4;
+
+/// Permissionless. The batch form of refresh_metadata, for NFTs resolved
+/// against the same version of the offset tiers
+#[derive(Accounts)]
+pub struct RefreshMetadataBatch<'info> {
+    /// CHECK: Verified with function
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: UncheckedAccount<'info>,
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(offset_tiers.version).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    pub token_metadata_program: Program<'info, MplTokenMetadata>,
+}
+
+/// Loads the offset metadata of an nft passed in the remaining accounts,
+/// checking its pda, its metadata account and that it was resolved against the given tiers version
+pub(crate) fn load_offset_metadata<'info>(
+    offset_metadata: &'info AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    metadata: &AccountInfo<'info>,
+    global_state: &Pubkey,
+    tiers_version: u16,
+) -> Result<Account<'info, OffsetMetadata>> {
+    let account = Account::<OffsetMetadata>::try_from(offset_metadata)?;
+    let expected_offset_metadata = Pubkey::create_program_address(
+        &[
+            OFFSET_METADATA_SEED,
+            mint.key.as_ref(),
+            global_state.as_ref(),
+            &[account.bump],
+        ],
+        &crate::ID,
+    )
+    .map_err(|_| ErrorCode::InvalidOffsetMetadata)?;
+    require_keys_eq!(
+        offset_metadata.key(),
+        expected_offset_metadata,
+        ErrorCode::InvalidOffsetMetadata
+    );
+    require_eq!(account.tiers_version, tiers_version, ErrorCode::InvalidOffsetMetadata);
+    check_metadata_account(metadata, mint)?;
+    Ok(account)
+}
+
+/// Reads the owner of an nft from its token account passed in the remaining accounts.
+/// The program id stands in for an omitted token account
+pub(crate) fn load_owner<'info>(
+    owner_token_account: &'info AccountInfo<'info>,
+    mint: &Pubkey,
+) -> Result<Option<Pubkey>> {
+    if owner_token_account.key() == crate::ID {
+        return Ok(None);
+    }
+    let owner_token_account = Account::<TokenAccount>::try_from(owner_token_account)?;
+    require!(
+        owner_token_account.mint == *mint && owner_token_account.amount == 1,
+        ErrorCode::InvalidOwnerTokenAccount
+    );
+    Ok(Some(owner_token_account.owner))
+}
+
+pub fn refresh_metadata_batch_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RefreshMetadataBatch<'info>>,
+) -> Result<()> {
+    let global_state_key = ctx.accounts.global_state.key();
+    let offset_tiers = &ctx.accounts.offset_tiers;
+
+    let groups = ctx.remaining_accounts.chunks_exact(REFRESH_ACCOUNTS);
+    require!(
+        !ctx.remaining_accounts.is_empty() && groups.remainder().is_empty(),
+        ErrorCode::InvalidOffsetMetadata
+    );
+
+    for accounts in groups {
+        let [offset_metadata_info, mint, metadata, owner_token_account] = accounts else {
+            unreachable!()
+        };
+
+        let offset_metadata = load_offset_metadata(
+            offset_metadata_info,
+            mint,
+            metadata,
+            &global_state_key,
+            offset_tiers.version,
+        )?;
+        let owner = load_owner(owner_token_account, mint.key)?;
+
+        let level_index = offset_metadata.current_level_index as usize;
+        msg!("Refreshing metadata of {}", mint.key());
+        update_metadata(
+            &offset_tiers.levels[level_index],
+            &UriParams {
+                offset: offset_metadata.offset,
+                level_index,
+                mint: mint.key(),
+                owner,
+            },
+            metadata.clone(),
+            &global_state_key,
+            ctx.accounts.token_authority.to_account_info(),
+            ctx.bumps.token_authority,
+            ctx.accounts.token_metadata_program.to_account_info(),
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use crate::error::ErrorCode;
+use crate::seeds::FEE_WAIVER_SEED;
+use crate::state::{FeeWaiver, GlobalState};
+use anchor_lang::prelude::*;
+
+/// Permissioned. Requires the admin_update_authority.
+/// Closes a fee waiver, returning its rent to the admin_update_authority
+#[derive(Accounts)]
+pub struct RemoveFeeWaiver<'info> {
+    #[account(mut)]
+    pub admin_update_authority: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        close = admin_update_authority,
+        seeds = [FEE_WAIVER_SEED, global_state.key().as_ref(), fee_waiver.key.as_ref()],
+        bump = fee_waiver.bump,
+    )]
+    pub fee_waiver: Account<'info, FeeWaiver>,
+}
+
+pub fn remove_fee_waiver_handler(ctx: Context<RemoveFeeWaiver>) -> Result<()> {
+    msg!("Removing fee waiver for {}", ctx.accounts.fee_waiver.key);
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use crate::error::ErrorCode;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetTiers};
+use crate::utils::tiers::validate_levels;
+use anchor_lang::prelude::*;
+
+/// Permissioned. Requires the admin_update_authority.
+/// The rent freed by the removed level is returned to the payer
+#[derive(Accounts)]
+pub struct RemoveLevel<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
+        bump,
+        realloc = OffsetTiers::space(offset_tiers.levels.len().saturating_sub(1)),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        realloc = LevelCounts::space(offset_tiers.levels.len().saturating_sub(1)),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn remove_level_handler(ctx: Context<RemoveLevel>, index: u16) -> Result<()> {
+    let offset_tiers = &mut ctx.accounts.offset_tiers;
+    let level_counts = &mut ctx.accounts.level_counts;
+    let index = index as usize;
+    // legacy NFTs refer to their level by an index that is not counted until they are upgraded
+    require_eq!(
+        level_counts.pending_legacy_upgrades,
+        0,
+        ErrorCode::LegacyUpgradesPending
+    );
+
+    require_gt!(
+        offset_tiers.levels.len(),
+        index,
+        ErrorCode::InvalidLevelIndex
+    );
+    // NFTs at the removed level would be orphaned, and those above it shifted down
+    require_eq!(level_counts.nfts_from(index), 0, ErrorCode::LevelInUse);
+
+    offset_tiers.levels.remove(index);
+    level_counts.counts.remove(index);
+    validate_levels(&offset_tiers.levels, &ctx.accounts.global_state.fee_distribution)?;
+
+    msg!("Removed level at index {}", index);
+    Ok(())
+}
@@ -1,8 +1,12 @@
 use crate::error::ErrorCode;
-use crate::state::{GlobalState, GlobalStateUpdateInput};
+use crate::state::{GlobalState, GlobalStateUpdateInput, PendingFeeUpdate};
+use crate::utils::fee::validate_fees;
+use crate::utils::tiers::levels_charge_fees;
 use anchor_lang::prelude::*;
 
-/// Permissioned. Requires the admin_update_authority
+/// Permissioned. Requires the admin_update_authority.
+/// Removing the fee distribution requires every version of the offset tiers
+/// in the remaining accounts, in order, to check that none of their levels charges a fee
 #[derive(Accounts, Clone)]
 #[instruction(state: GlobalStateUpdateInput)]
 pub struct UpdateGlobalState<'info> {
@@ -18,9 +22,54 @@ pub fn update_global_state_handler(
     ctx: Context<UpdateGlobalState>,
     state: GlobalStateUpdateInput,
 ) -> Result<()> {
+    validate_fees(&[&state.fee, &state.update_fee], &state.fee_distribution)?;
+
+    let slot = Clock::get()?.slot;
     let global_state = &mut ctx.accounts.global_state;
+    // a fee update that has already activated must not be overwritten by a new pending one
+    global_state.activate_pending_fee_update(slot);
+
+    // fees charged without a distribution could never be withdrawn from the vaults
+    if state.fee_distribution.is_none() && global_state.fee_distribution.is_some() {
+        let scheduled = state
+            .fee_activation_slot
+            .is_some_and(|activation_slot| activation_slot > slot);
+        require!(
+            !scheduled || (global_state.fee.is_none() && global_state.update_fee.is_none()),
+            ErrorCode::FeeDistributionInUse
+        );
+        require!(
+            !levels_charge_fees(
+                &global_state.key(),
+                global_state.tiers_version,
+                ctx.remaining_accounts
+            )?,
+            ErrorCode::FeeDistributionInUse
+        );
+    }
+
     global_state.admin_update_authority = state.admin_update_authority.key();
     global_state.admin_mint_authority = state.admin_mint_authority.key();
     global_state.levels = state.levels;
+    global_state.fee_distribution = state.fee_distribution;
+    global_state.downgrade_policy = state.downgrade_policy;
+
+    match state.fee_activation_slot {
+        Some(activation_slot) if activation_slot > slot => {
+            msg!("Fee update scheduled for slot {}", activation_slot);
+            global_state.pending_fee_update = Some(PendingFeeUpdate {
+                fee: state.fee,
+                update_fee: state.update_fee,
+                update_fee_scaling: state.update_fee_scaling,
+                activation_slot,
+            });
+        }
+        _ => {
+            global_state.fee = state.fee;
+            global_state.update_fee = state.update_fee;
+            global_state.update_fee_scaling = state.update_fee_scaling;
+            global_state.pending_fee_update = None;
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,73 @@
+use crate::error::ErrorCode;
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, Level, LevelCounts, OffsetTiers};
+use crate::utils::tiers::{validate_levels, verify_collections};
+use anchor_lang::prelude::*;
+
+/// Permissioned. Requires the admin_update_authority
+#[derive(Accounts)]
+pub struct UpdateLevel<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+}
+
+pub fn update_level_handler(ctx: Context<UpdateLevel>, index: u16, level: Level) -> Result<()> {
+    let offset_tiers = &mut ctx.accounts.offset_tiers;
+    let level_counts = &ctx.accounts.level_counts;
+    let index = index as usize;
+    // legacy NFTs refer to their level by an index that is not counted until they are upgraded
+    require_eq!(
+        level_counts.pending_legacy_upgrades,
+        0,
+        ErrorCode::LegacyUpgradesPending
+    );
+
+    require_gt!(
+        offset_tiers.levels.len(),
+        index,
+        ErrorCode::InvalidLevelIndex
+    );
+    // NFTs at this level are verified members of its collection
+    if level_counts.count(index) > 0 {
+        require_keys_eq!(
+            offset_tiers.levels[index].collection_mint,
+            level.collection_mint,
+            ErrorCode::LevelInUse
+        );
+    }
+
+    verify_collections(
+        std::slice::from_ref(&level),
+        &offset_tiers.levels,
+        ctx.accounts.token_authority.key,
+        ctx.remaining_accounts,
+    )?;
+    offset_tiers.levels[index] = level;
+    validate_levels(&offset_tiers.levels, &ctx.accounts.global_state.fee_distribution)?;
+
+    msg!("Updated level at index {}", index);
+    Ok(())
+}
@@ -1,9 +1,12 @@
 use crate::error::ErrorCode;
-use crate::seeds::{OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
-use crate::state::{GlobalState, OffsetMetadata, OffsetTiers};
-use crate::utils::metaplex::{check_metadata_account, unverify_nft, update_metadata, verify_nft};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{DowngradePolicy, FeeVault, FeeWaiver, GlobalState, Level, LevelCounts, LevelUpFeeScaling, OffsetMetadata, OffsetTiers};
+use crate::utils::fee::{apply_discount, calculate_fee, get_fee_discount, handle_fees, FeeAccounts};
+use crate::utils::metaplex::{check_metadata_account, unverify_nft, update_metadata, verify_nft, UriParams};
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar;
 use anchor_spl::token::{Mint, Token};
+use anchor_spl::token_interface::{self, TokenAccount, TokenInterface};
 use crate::external_programs::mpl_token_metadata::MplTokenMetadata;
 
 /// Permissionless. Requires the external admin_mint_authority
@@ -26,10 +29,20 @@ pub struct UpdateNft<'info> {
     )]
     pub global_state: Account<'info, GlobalState>,
     #[account(
-        seeds = [OFFSET_TIERS_SEED, global_state.key().as_ref()],
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(offset_metadata.tiers_version).as_ref(),
+        ],
         bump,
     )]
     pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
     #[account(
         mut,
         seeds = [OFFSET_METADATA_SEED, mint.key().as_ref(), global_state.key().as_ref()],
@@ -42,9 +55,16 @@ pub struct UpdateNft<'info> {
     /// CHECK: Verified with the check_metadata_account helper function
     #[account(
         mut,
-        constraint = check_metadata_account(&metadata, &mint.to_account_info()),
+        constraint = check_metadata_account(&metadata, &mint.to_account_info()).is_ok()
+            @ ErrorCode::InvalidMetadata,
     )]
     pub metadata: UncheckedAccount<'info>,
+    // only required if the level's uri contains the {owner} placeholder
+    #[account(
+        token::mint = mint,
+        constraint = owner_token_account.amount == 1 @ ErrorCode::InvalidOwnerTokenAccount,
+    )]
+    pub owner_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     /// CHECK: Checked by instruction
     pub new_collection_mint: UncheckedAccount<'info>,
@@ -66,6 +86,29 @@ pub struct UpdateNft<'info> {
 
     pub token_program: Program<'info, Token>,
     pub token_metadata_program: Program<'info, MplTokenMetadata>,
+    pub system_program: Program<'info, System>,
+
+    // only required if an update fee is configured
+    #[account(
+        mut,
+        has_one = global_state @ ErrorCode::InvalidFeeVault,
+    )]
+    pub fee_vault: Option<Box<Account<'info, FeeVault>>>,
+    // only required for SPL fees
+    #[account(mut)]
+    pub fee_vault_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut)]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub fee_mint: Option<Box<InterfaceAccount<'info, token_interface::Mint>>>,
+    pub fee_token_program: Option<Interface<'info, TokenInterface>>,
+    // waives or discounts the fee for the payer or the calling program
+    #[account(
+        has_one = global_state @ ErrorCode::InvalidFeeWaiver,
+    )]
+    pub fee_waiver: Option<Box<Account<'info, FeeWaiver>>>,
+    /// CHECK: The instructions sysvar, only required for fee waivers keyed by the calling program
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
 }
 
 // Todo: Run a check to see if this is needed by attempting to unverify from a
@@ -78,24 +121,86 @@ fn calculate_current_collection_key<'a>(
     offset_tiers.levels[index as usize].collection_mint
 }
 
-fn calculate_new_collection_key(offset_tiers: &Account<'_, OffsetTiers>, offset_amount: u64) -> Pubkey {
-    offset_tiers
-        .get_level(offset_amount)
-        .unwrap()
-        .collection_mint
+pub(crate) fn calculate_new_level_index(
+    global_state: &GlobalState,
+    offset_metadata: &OffsetMetadata,
+    offset_tiers: &OffsetTiers,
+    offset_amount: u64,
+    principal: u64,
+    now: i64,
+) -> Result<usize> {
+    let offset_level_index = offset_tiers
+        .get_index(offset_amount, principal)
+        .unwrap_or(0);
+    let target_level_index = match global_state.downgrade_policy {
+        DowngradePolicy::Allow => offset_level_index,
+        DowngradePolicy::Forbid => {
+            require_gte!(
+                offset_level_index,
+                offset_metadata.current_level_index as usize,
+                ErrorCode::DowngradeForbidden
+            );
+            offset_level_index
+        }
+        DowngradePolicy::KeepHighest => {
+            offset_level_index.max(offset_metadata.highest_level_index as usize)
+        }
+    };
+    let time_at_current_level = now.saturating_sub(offset_metadata.level_reached_at).max(0) as u64;
+    Ok(offset_tiers.cap_promotion(
+        offset_metadata.current_level_index as usize,
+        target_level_index,
+        time_at_current_level,
+    ))
+}
+
+/// Level-ups are charged the new level's fee, or the global update fee, downgrades are free.
+/// Percentage update fees are calculated on the principal, like mint fees
+pub(crate) fn charge_update_fee(
+    global_state: &GlobalState,
+    new_level: &Level,
+    current_level_index: usize,
+    new_level_index: usize,
+    principal: u64,
+    fee_waiver: Option<&Account<FeeWaiver>>,
+    instructions_sysvar: Option<&AccountInfo>,
+    fee_accounts: FeeAccounts,
+    max_fee_payable: u64,
+) -> Result<()> {
+    let (global_update_fee, update_fee_scaling) = global_state.update_fee_at(Clock::get()?.slot);
+    let update_fee = new_level
+        .fee_config(global_update_fee)
+        .filter(|_| new_level_index > current_level_index);
+    let Some(fee_config) = update_fee else {
+        return Ok(());
+    };
+    let fee = calculate_fee(fee_config, principal)?;
+    let amount = match update_fee_scaling {
+        LevelUpFeeScaling::Flat => fee,
+        LevelUpFeeScaling::PerLevel => fee
+            .checked_mul((new_level_index - current_level_index) as u64)
+            .ok_or(ErrorCode::FeeCalculationOverflow)?,
+    };
+    let discount = get_fee_discount(
+        fee_waiver,
+        &fee_accounts.fee_payer.key(),
+        instructions_sysvar,
+    )?;
+    let amount = apply_discount(amount, discount)?;
+    handle_fees(fee_config, fee_accounts, amount, max_fee_payable)
 }
 
 /** TODO: review edge cases */
-pub fn update_nft_handler(ctx: Context<UpdateNft>, offset_amount: u64) -> Result<()> {
+pub fn update_nft_handler(
+    ctx: Context<UpdateNft>,
+    offset_amount: u64,
+    principal: u64,
+    max_fee_payable: u64,
+) -> Result<()> {
     let offset_metadata = &mut ctx.accounts.offset_metadata;
     let offset_tiers = &ctx.accounts.offset_tiers;
     let metadata = &ctx.accounts.metadata;
 
-    let current_collection_key = calculate_current_collection_key(offset_metadata, offset_tiers);
-    let new_collection_key = calculate_new_collection_key(offset_tiers, offset_amount);
-    require_keys_eq!(*ctx.accounts.collection_mint.key, current_collection_key);
-    require_keys_eq!(*ctx.accounts.new_collection_mint.key, new_collection_key);
-
     let token_authority = &ctx.accounts.token_authority;
     let global_state = &ctx.accounts.global_state;
     let token_authority_bump = ctx.bumps.token_authority;
@@ -105,17 +210,51 @@ pub fn update_nft_handler(ctx: Context<UpdateNft>, offset_amount: u64) -> Result
         return Err(ErrorCode::NoOffsetTiers.into());
     }
 
-    let new_level_index = offset_tiers
-        .get_index_from_offset(offset_amount)
-        .unwrap_or(0);
-    if new_level_index == offset_metadata.current_level_index as usize {
-        // no need to update
+    let now = Clock::get()?.unix_timestamp;
+    let new_level_index = calculate_new_level_index(
+        global_state,
+        offset_metadata,
+        offset_tiers,
+        offset_amount,
+        principal,
+        now,
+    )?;
+    let current_collection_key = calculate_current_collection_key(offset_metadata, offset_tiers);
+    require_keys_eq!(*ctx.accounts.collection_mint.key, current_collection_key);
+    require_keys_eq!(
+        *ctx.accounts.new_collection_mint.key,
+        offset_tiers.levels[new_level_index].collection_mint
+    );
+
+    // the offset is recorded on every update, the metadata only changes with the level
+    let current_level_index = offset_metadata.current_level_index as usize;
+    offset_metadata.record_update(offset_amount, principal, now);
+    if new_level_index == current_level_index {
         return Ok(());
     }
     let new_level = &offset_tiers.levels[new_level_index];
 
+    charge_update_fee(
+        global_state,
+        new_level,
+        current_level_index,
+        new_level_index,
+        principal,
+        ctx.accounts.fee_waiver.as_deref(),
+        ctx.accounts.instructions_sysvar.as_deref(),
+        FeeAccounts {
+            fee_payer: &ctx.accounts.payer,
+            fee_vault: ctx.accounts.fee_vault.as_deref_mut(),
+            fee_vault_token_account: ctx.accounts.fee_vault_token_account.as_deref(),
+            fee_token_program: ctx.accounts.fee_token_program.as_ref(),
+            fee_mint: ctx.accounts.fee_mint.as_deref(),
+            fee_payer_token_account: ctx.accounts.payer_token_account.as_deref(),
+        },
+        max_fee_payable,
+    )?;
+
     if **ctx.accounts.mint.to_account_info().try_borrow_lamports()? > 0 {
-        offset_metadata.set_amount(offset_amount);
+        offset_metadata.set_level_reached_at(now);
         offset_metadata.set_level_index(new_level_index);
 
         msg!("Unverifying...");
@@ -133,6 +272,12 @@ pub fn update_nft_handler(ctx: Context<UpdateNft>, offset_amount: u64) -> Result
         msg!("Updating...");
         update_metadata(
             new_level,
+            &UriParams {
+                offset: offset_amount,
+                level_index: new_level_index,
+                mint: ctx.accounts.mint.key(),
+                owner: ctx.accounts.owner_token_account.as_ref().map(|account| account.owner),
+            },
             metadata.to_account_info(),
             &global_state.key(),
             ctx.accounts.token_authority.to_account_info(),
@@ -151,6 +296,9 @@ pub fn update_nft_handler(ctx: Context<UpdateNft>, offset_amount: u64) -> Result
             token_authority_bump,
             ctx.accounts.token_metadata_program.to_account_info(),
         )?;
+        ctx.accounts
+            .level_counts
+            .record_level_change(Some(current_level_index), new_level_index);
     } else {
         return Err(ErrorCode::InvalidUpdateForMint.into());
     }
@@ -1,31 +1,88 @@
 use crate::error::ErrorCode;
-use crate::seeds::OFFSET_TIERS_SEED;
-use crate::state::{GlobalState, OffsetTiers, OffsetTiersInput};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED, TOKEN_AUTHORITY_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetTiers, OffsetTiersInput};
+use crate::utils::tiers::{validate_levels, verify_collections};
 use anchor_lang::prelude::*;
 
 /// Permissioned. Requires the admin_update_authority
-/// for verification
+/// for verification. The payer covers rent when the
+/// offset tiers account grows, and is refunded when it shrinks
 #[derive(Accounts, Clone)]
 #[instruction(state: OffsetTiersInput)]
 pub struct UpdateOffsetTiers<'info> {
     pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
     #[account(
         has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [TOKEN_AUTHORITY_SEED, global_state.key().as_ref()],
+        bump
+    )]
+    pub token_authority: SystemAccount<'info>,
     #[account(
         mut,
-        seeds = [OFFSET_TIERS_SEED, global_state.key().as_ref()],
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(global_state.tiers_version).as_ref(),
+        ],
         bump,
+        realloc = OffsetTiers::space(state.levels.len()),
+        realloc::payer = payer,
+        realloc::zero = false,
     )]
     pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        realloc = LevelCounts::space(state.levels.len()),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    pub system_program: Program<'info, System>,
 }
 
 pub fn update_offset_tiers_handler(
     ctx: Context<UpdateOffsetTiers>,
     state: OffsetTiersInput,
 ) -> Result<()> {
+    validate_levels(&state.levels, &ctx.accounts.global_state.fee_distribution)?;
     let offset_tiers = &mut ctx.accounts.offset_tiers;
+    verify_collections(
+        &state.levels,
+        &offset_tiers.levels,
+        ctx.accounts.token_authority.key,
+        ctx.remaining_accounts,
+    )?;
+    // levels beyond the new end must not hold any NFTs
+    // and NFTs at the remaining levels are verified members of their collections
+    let level_counts = &mut ctx.accounts.level_counts;
+    require_eq!(
+        level_counts.pending_legacy_upgrades,
+        0,
+        ErrorCode::LegacyUpgradesPending
+    );
+    let kept_levels = offset_tiers.levels.iter().zip(&state.levels);
+    for (index, (old_level, new_level)) in kept_levels.enumerate() {
+        if level_counts.count(index) > 0 {
+            require_keys_eq!(
+                old_level.collection_mint,
+                new_level.collection_mint,
+                ErrorCode::LevelInUse
+            );
+        }
+    }
+    require_eq!(
+        level_counts.nfts_from(state.levels.len()),
+        0,
+        ErrorCode::LevelInUse
+    );
+    level_counts.resize(state.levels.len());
     offset_tiers.set(state);
     Ok(())
 }
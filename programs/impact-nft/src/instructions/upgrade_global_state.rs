@@ -0,0 +1,50 @@
+use crate::error::ErrorCode;
+use crate::legacy::{load_legacy, store_upgraded, LegacyGlobalState};
+use crate::state::{FeeDistribution, GlobalState};
+use crate::utils::fee::validate_fees;
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+/// Permissioned. Requires the admin_update_authority of the legacy global state.
+/// Rewrites a global state created by the deployed program in the current layout.
+/// The legacy fee recipient is replaced by the given fee distribution,
+/// the new settings start at their defaults
+#[derive(Accounts)]
+pub struct UpgradeGlobalState<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: A legacy global state, checked in the handler
+    #[account(mut)]
+    pub global_state: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn upgrade_global_state_handler(
+    ctx: Context<UpgradeGlobalState>,
+    fee_distribution: Option<FeeDistribution>,
+) -> Result<()> {
+    let legacy: LegacyGlobalState = load_legacy(
+        &ctx.accounts.global_state,
+        GlobalState::DISCRIMINATOR,
+        LegacyGlobalState::SPACE,
+    )?;
+    require_keys_eq!(
+        legacy.admin_update_authority,
+        ctx.accounts.admin_update_authority.key(),
+        ErrorCode::InvalidAdminAuthority
+    );
+
+    let global_state = legacy.upgrade(fee_distribution);
+    validate_fees(&[&global_state.fee], &global_state.fee_distribution)?;
+    store_upgraded(
+        &ctx.accounts.global_state,
+        &global_state,
+        GlobalState::SPACE,
+        &ctx.accounts.payer,
+        &ctx.accounts.system_program,
+    )?;
+
+    msg!("Upgraded global state {}", ctx.accounts.global_state.key());
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use crate::error::ErrorCode;
+use crate::legacy::{load_legacy, store_upgraded, LegacyOffsetMetadata};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_METADATA_SEED, OFFSET_TIERS_SEED};
+use crate::state::{GlobalState, LevelCounts, OffsetMetadata, OffsetTiers};
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use anchor_spl::token::Mint;
+
+/// Permissionless, after upgrade_offset_tiers.
+/// Rewrites the offset metadata of an NFT minted by the deployed program,
+/// against version 0 of the offset tiers, and counts the NFT at its level.
+/// The principal and timestamps of legacy NFTs are unknown, they start at zero
+/// and the time of the upgrade
+#[derive(Accounts)]
+pub struct UpgradeOffsetMetadata<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(0).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: Account<'info, OffsetTiers>,
+    #[account(
+        mut,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    /// CHECK: The legacy offset metadata of the mint, checked in the handler
+    #[account(
+        mut,
+        seeds = [OFFSET_METADATA_SEED, mint.key().as_ref(), global_state.key().as_ref()],
+        bump,
+    )]
+    pub offset_metadata: UncheckedAccount<'info>,
+    pub mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn upgrade_offset_metadata_handler(ctx: Context<UpgradeOffsetMetadata>) -> Result<()> {
+    let legacy: LegacyOffsetMetadata = load_legacy(
+        &ctx.accounts.offset_metadata,
+        OffsetMetadata::DISCRIMINATOR,
+        LegacyOffsetMetadata::SPACE,
+    )?;
+    let level_index = legacy.current_level_index as usize;
+    require_gt!(
+        ctx.accounts.offset_tiers.levels.len(),
+        level_index,
+        ErrorCode::InvalidLevelIndex
+    );
+
+    let offset_metadata = legacy.upgrade(Clock::get()?.unix_timestamp);
+    store_upgraded(
+        &ctx.accounts.offset_metadata,
+        &offset_metadata,
+        OffsetMetadata::SPACE,
+        &ctx.accounts.payer,
+        &ctx.accounts.system_program,
+    )?;
+    let level_counts = &mut ctx.accounts.level_counts;
+    level_counts.add_nft(level_index);
+    level_counts.pending_legacy_upgrades = level_counts.pending_legacy_upgrades.saturating_sub(1);
+
+    msg!("Upgraded offset metadata of {}", ctx.accounts.mint.key());
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::error::ErrorCode;
+use crate::legacy::{load_legacy, store_upgraded, LegacyLevel, LegacyOffsetTiers};
+use crate::seeds::{offset_tiers_version_seed, LEVEL_COUNTS_SEED, OFFSET_TIERS_SEED};
+use crate::state::{GlobalState, Level, LevelCounts, OffsetTiers};
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+/// Permissioned. Requires the admin_update_authority, after upgrade_global_state.
+/// Rewrites the offset tiers created by the deployed program as version 0 of the
+/// offset tiers, and creates its level counts. The NFTs at each level are counted
+/// as their offset metadata is upgraded, and the levels cannot be edited until
+/// all of the given number of legacy NFTs are upgraded
+#[derive(Accounts)]
+pub struct UpgradeOffsetTiers<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    /// CHECK: The legacy offset tiers, checked in the handler
+    #[account(
+        mut,
+        seeds = [
+            OFFSET_TIERS_SEED,
+            global_state.key().as_ref(),
+            offset_tiers_version_seed(0).as_ref(),
+        ],
+        bump,
+    )]
+    pub offset_tiers: UncheckedAccount<'info>,
+    // reallocated to the number of levels by later level changes
+    #[account(
+        init,
+        seeds = [LEVEL_COUNTS_SEED, offset_tiers.key().as_ref()],
+        bump,
+        payer = payer,
+        space = LevelCounts::space(LegacyOffsetTiers::MAX_LEVELS),
+    )]
+    pub level_counts: Account<'info, LevelCounts>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn upgrade_offset_tiers_handler(
+    ctx: Context<UpgradeOffsetTiers>,
+    legacy_nfts: u64,
+) -> Result<()> {
+    let legacy: LegacyOffsetTiers = load_legacy(
+        &ctx.accounts.offset_tiers,
+        OffsetTiers::DISCRIMINATOR,
+        LegacyOffsetTiers::SPACE,
+    )?;
+
+    let levels: Vec<Level> = legacy.levels.into_iter().map(LegacyLevel::upgrade).collect();
+    let level_counts = &mut ctx.accounts.level_counts;
+    level_counts.set(levels.len(), ctx.bumps.level_counts);
+    level_counts.pending_legacy_upgrades = legacy_nfts;
+
+    let space = OffsetTiers::space(levels.len());
+    let offset_tiers = OffsetTiers {
+        levels,
+        bump: ctx.bumps.offset_tiers,
+        version: 0,
+    };
+    store_upgraded(
+        &ctx.accounts.offset_tiers,
+        &offset_tiers,
+        space,
+        &ctx.accounts.payer,
+        &ctx.accounts.system_program,
+    )?;
+
+    msg!("Upgraded offset tiers with {} levels", offset_tiers.levels.len());
+    Ok(())
+}
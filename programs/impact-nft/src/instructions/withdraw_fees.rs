@@ -0,0 +1,48 @@
+use crate::error::ErrorCode;
+use crate::state::{FeeVault, GlobalState};
+use crate::utils::fee::withdraw_from_vault;
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+/// Permissioned. Requires the admin_update_authority.
+/// Sweeps a fee vault, splitting its balance between the recipients of the fee distribution.
+/// The recipients are passed as remaining accounts in the order of the fee distribution:
+/// their SOL addresses for the SOL vault, or their associated token accounts for a token vault
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub admin_update_authority: Signer<'info>,
+    #[account(
+        has_one = admin_update_authority @ ErrorCode::InvalidAdminAuthority,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        has_one = global_state @ ErrorCode::InvalidFeeVault,
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+    // only required for token vaults
+    #[account(mut)]
+    pub fee_vault_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub fee_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub fee_token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+pub fn withdraw_fees_handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, WithdrawFees<'info>>,
+) -> Result<()> {
+    let fee_distribution = ctx
+        .accounts
+        .global_state
+        .fee_distribution
+        .as_ref()
+        .ok_or(ErrorCode::NoFeeDistribution)?;
+
+    withdraw_from_vault(
+        fee_distribution,
+        &mut ctx.accounts.fee_vault,
+        ctx.accounts.fee_vault_token_account.as_ref(),
+        ctx.accounts.fee_token_program.as_ref(),
+        ctx.accounts.fee_mint.as_ref(),
+        ctx.remaining_accounts,
+    )
+}
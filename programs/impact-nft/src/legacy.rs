@@ -0,0 +1,292 @@
+/*
+Account layouts written by the deployed program before fee distributions,
+versioned offset tiers and the extended offset metadata were introduced.
+The accounts keep their discriminators, only their data changed, so they
+are upgraded in place by reallocating and rewriting them:
+
+1. upgrade_global_state, signed by the admin_update_authority
+2. upgrade_offset_tiers, signed by the admin_update_authority,
+   which also creates the level counts of version 0 and records
+   the number of legacy NFTs still to be upgraded
+3. upgrade_offset_metadata, permissionless, once for each existing NFT,
+   which also counts the NFT at its level
+
+Every other instruction rejects the legacy accounts until they are upgraded.
+Legacy NFTs refer to their level by index, but are only counted at it once
+upgraded, so the levels of version 0 cannot be edited until every legacy NFT
+is upgraded. Creating a new version of the offset tiers is allowed, the
+upgraded NFTs are then migrated to it.
+*/
+use crate::error::ErrorCode;
+use crate::state::{
+    CoinType, DowngradePolicy, FeeConfig, FeeDistribution, FeeType, GlobalState, Level,
+    LevelUpFeeScaling, OffsetMetadata,
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::system_instruction;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyFeeConfig {
+    pub fee: u64,
+    pub recipient: Pubkey,
+    pub fee_type: FeeType,
+    pub coin_type: CoinType,
+    pub spl_token_mint: Option<Pubkey>,
+}
+impl LegacyFeeConfig {
+    pub const SPACE: usize = 8 + 32 + 1 + 1 + (1 + 32);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyGlobalState {
+    pub admin_update_authority: Pubkey,
+    pub admin_mint_authority: Pubkey,
+    pub levels: u16,
+    pub fee: Option<LegacyFeeConfig>,
+}
+impl LegacyGlobalState {
+    pub const SPACE: usize = 8 + 32 + 32 + 2 + 1 + LegacyFeeConfig::SPACE;
+
+    /// The legacy fee keeps its amount and type, without bounds
+    pub fn upgrade(self, fee_distribution: Option<FeeDistribution>) -> GlobalState {
+        GlobalState {
+            admin_update_authority: self.admin_update_authority,
+            admin_mint_authority: self.admin_mint_authority,
+            levels: self.levels,
+            fee: self.fee.map(|fee| FeeConfig {
+                fee: fee.fee,
+                fee_type: fee.fee_type,
+                coin_type: fee.coin_type,
+                spl_token_mint: fee.spl_token_mint,
+                min_fee: 0,
+                max_fee: None,
+            }),
+            fee_distribution,
+            update_fee: None,
+            update_fee_scaling: LevelUpFeeScaling::Flat,
+            pending_fee_update: None,
+            tiers_version: 0,
+            // the deployed program moved NFTs to the level of their offset
+            downgrade_policy: DowngradePolicy::Allow,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyLevel {
+    pub offset: u64,
+    pub uri: String,
+    pub name: String,
+    pub symbol: String,
+    pub collection_mint: Pubkey,
+}
+impl LegacyLevel {
+    pub const SPACE: usize = 8 + (4 + 200) + (4 + 32) + (4 + 10) + 15 + 32;
+
+    /// The level is carried over as it is, with the new settings at their defaults
+    pub fn upgrade(self) -> Level {
+        Level {
+            offset: self.offset,
+            min_principal: 0,
+            min_duration_at_previous_level: 0,
+            uri: self.uri,
+            name: self.name,
+            symbol: self.symbol,
+            collection_mint: self.collection_mint,
+            fee: None,
+            royalty_basis_points: None,
+            creators: vec![],
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyOffsetTiers {
+    pub levels: Vec<LegacyLevel>,
+    pub bump: u8,
+}
+impl LegacyOffsetTiers {
+    pub const MAX_LEVELS: usize = 10;
+    pub const SPACE: usize = 4 + (LegacyLevel::SPACE * Self::MAX_LEVELS) + 1 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyOffsetMetadata {
+    pub current_level_index: u16,
+    pub offset: u64,
+    pub bump: u8,
+}
+impl LegacyOffsetMetadata {
+    pub const SPACE: usize = 8 + 2 + 8 + 1;
+
+    /// Places the NFT at its legacy level of version 0, which is also its highest level
+    pub fn upgrade(self, now: i64) -> OffsetMetadata {
+        OffsetMetadata {
+            current_level_index: self.current_level_index,
+            offset: self.offset,
+            bump: self.bump,
+            principal: 0,
+            tiers_version: 0,
+            highest_level_index: self.current_level_index,
+            minted_at: now,
+            level_reached_at: now,
+            last_updated: now,
+            update_count: 0,
+        }
+    }
+}
+
+/// Reads a legacy account of this program. The legacy layouts are told apart
+/// from the current ones by the discriminator of the current account type
+/// and the size of the legacy allocation
+pub fn load_legacy<T: AnchorDeserialize>(
+    account: &AccountInfo,
+    discriminator: [u8; 8],
+    space: usize,
+) -> Result<T> {
+    require_keys_eq!(*account.owner, crate::ID, ErrorCode::NotALegacyAccount);
+    let data = account.try_borrow_data()?;
+    require_eq!(data.len(), space, ErrorCode::NotALegacyAccount);
+    require!(data[..8] == discriminator, ErrorCode::NotALegacyAccount);
+    T::deserialize(&mut &data[8..]).map_err(|_| error!(ErrorCode::NotALegacyAccount))
+}
+
+/// Resizes a legacy account for its current layout and writes the upgraded account.
+/// The payer tops up the rent of the larger account
+pub fn store_upgraded<'info, T: AccountSerialize>(
+    account: &AccountInfo<'info>,
+    upgraded: &T,
+    space: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let rent_exempt_lamports = Rent::get()?.minimum_balance(space);
+    let top_up = rent_exempt_lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    account.realloc(space, true)?;
+
+    let mut data = account.try_borrow_mut_data()?;
+    upgraded.try_serialize(&mut &mut data[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::OffsetTiers;
+    use anchor_lang::Discriminator;
+
+    fn legacy_offset_metadata() -> LegacyOffsetMetadata {
+        LegacyOffsetMetadata {
+            current_level_index: 2,
+            offset: 250,
+            bump: 254,
+        }
+    }
+
+    fn load_offset_metadata_with(
+        owner: Pubkey,
+        discriminator: [u8; 8],
+        space: usize,
+    ) -> Result<LegacyOffsetMetadata> {
+        let mut data = discriminator.to_vec();
+        legacy_offset_metadata().serialize(&mut data)?;
+        data.resize(space, 0);
+        let (key, mut lamports) = (Pubkey::new_unique(), 0);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        load_legacy(&account, OffsetMetadata::DISCRIMINATOR, LegacyOffsetMetadata::SPACE)
+    }
+
+    #[test]
+    fn loads_legacy_accounts_of_this_program() {
+        let legacy = load_offset_metadata_with(
+            crate::ID,
+            OffsetMetadata::DISCRIMINATOR,
+            LegacyOffsetMetadata::SPACE,
+        )
+        .unwrap();
+        assert_eq!(legacy.current_level_index, 2);
+        assert_eq!(legacy.offset, 250);
+        assert_eq!(legacy.bump, 254);
+    }
+
+    #[test]
+    fn rejects_accounts_that_are_not_legacy() {
+        let other_owner = load_offset_metadata_with(
+            Pubkey::new_unique(),
+            OffsetMetadata::DISCRIMINATOR,
+            LegacyOffsetMetadata::SPACE,
+        );
+        assert_eq!(other_owner.err(), Some(ErrorCode::NotALegacyAccount.into()));
+        let other_account_type = load_offset_metadata_with(
+            crate::ID,
+            GlobalState::DISCRIMINATOR,
+            LegacyOffsetMetadata::SPACE,
+        );
+        assert_eq!(other_account_type.err(), Some(ErrorCode::NotALegacyAccount.into()));
+        // an account that has already been upgraded
+        let upgraded = load_offset_metadata_with(
+            crate::ID,
+            OffsetMetadata::DISCRIMINATOR,
+            OffsetMetadata::SPACE,
+        );
+        assert_eq!(upgraded.err(), Some(ErrorCode::NotALegacyAccount.into()));
+    }
+
+    #[test]
+    fn upgraded_accounts_keep_their_legacy_values() {
+        let legacy_global_state = LegacyGlobalState {
+            admin_update_authority: Pubkey::new_unique(),
+            admin_mint_authority: Pubkey::new_unique(),
+            levels: 5,
+            fee: Some(LegacyFeeConfig {
+                fee: 100,
+                recipient: Pubkey::new_unique(),
+                fee_type: FeeType::Percentage,
+                coin_type: CoinType::Native,
+                spl_token_mint: None,
+            }),
+        };
+        let admin_update_authority = legacy_global_state.admin_update_authority;
+        let global_state = legacy_global_state.upgrade(None);
+        assert_eq!(global_state.admin_update_authority, admin_update_authority);
+        assert_eq!(global_state.levels, 5);
+        let fee = global_state.fee.unwrap();
+        assert_eq!((fee.fee, fee.min_fee, fee.max_fee), (100, 0, None));
+        assert_eq!(fee.fee_type, FeeType::Percentage);
+        assert_eq!(global_state.tiers_version, 0);
+        assert!(global_state.downgrade_policy == DowngradePolicy::Allow);
+
+        let offset_metadata = legacy_offset_metadata().upgrade(1_000);
+        assert_eq!(offset_metadata.current_level_index, 2);
+        assert_eq!(offset_metadata.highest_level_index, 2);
+        assert_eq!(offset_metadata.offset, 250);
+        assert_eq!(offset_metadata.principal, 0);
+        assert_eq!(offset_metadata.tiers_version, 0);
+        assert_eq!(offset_metadata.level_reached_at, 1_000);
+    }
+
+    // legacy accounts are recognised by their size, which no current account can have
+    #[test]
+    fn legacy_sizes_differ_from_the_current_layouts() {
+        assert_ne!(LegacyGlobalState::SPACE, GlobalState::SPACE);
+        assert_ne!(LegacyOffsetMetadata::SPACE, OffsetMetadata::SPACE);
+        for levels in 0..=LegacyOffsetTiers::MAX_LEVELS * 2 {
+            assert_ne!(LegacyOffsetTiers::SPACE, OffsetTiers::space(levels));
+        }
+    }
+}
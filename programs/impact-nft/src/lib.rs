@@ -8,6 +8,7 @@ pub mod state;
 use state::*;
 
 pub mod error;
+pub mod legacy;
 pub mod seeds;
 pub mod utils;
 
@@ -48,15 +49,106 @@ pub mod impact_nft {
         update_offset_tiers_handler(ctx, input)
     }
 
+    pub fn create_offset_tiers_version(
+        ctx: Context<CreateOffsetTiersVersion>,
+        input: OffsetTiersInput,
+    ) -> Result<()> {
+        create_offset_tiers_version_handler(ctx, input)
+    }
+
     pub fn add_levels(ctx: Context<AddLevels>, input: Vec<Level>) -> Result<()> {
         add_level_handler(ctx, input)
     }
 
-    pub fn mint_nft(ctx: Context<MintNft>, offset_amount: u64, principal: u64) -> Result<()> {
-        mint_nft_handler(ctx, offset_amount, principal)
+    pub fn insert_level(ctx: Context<InsertLevel>, index: u16, level: Level) -> Result<()> {
+        insert_level_handler(ctx, index, level)
+    }
+
+    pub fn remove_level(ctx: Context<RemoveLevel>, index: u16) -> Result<()> {
+        remove_level_handler(ctx, index)
+    }
+
+    pub fn update_level(ctx: Context<UpdateLevel>, index: u16, level: Level) -> Result<()> {
+        update_level_handler(ctx, index, level)
+    }
+
+    pub fn mint_nft(
+        ctx: Context<MintNft>,
+        offset_amount: u64,
+        principal: u64,
+        max_fee_payable: u64,
+    ) -> Result<()> {
+        mint_nft_handler(ctx, offset_amount, principal, max_fee_payable)
+    }
+
+    pub fn update_nft(
+        ctx: Context<UpdateNft>,
+        offset_amount: u64,
+        principal: u64,
+        max_fee_payable: u64,
+    ) -> Result<()> {
+        update_nft_handler(ctx, offset_amount, principal, max_fee_payable)
+    }
+
+    pub fn batch_update_nfts<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchUpdateNfts<'info>>,
+        updates: Vec<NftUpdateInput>,
+        max_fee_payable: u64,
+    ) -> Result<u16> {
+        batch_update_nfts_handler(ctx, updates, max_fee_payable)
+    }
+
+    pub fn migrate_nft(ctx: Context<MigrateNft>) -> Result<()> {
+        migrate_nft_handler(ctx)
+    }
+
+    pub fn burn_nft(ctx: Context<BurnNft>) -> Result<()> {
+        burn_nft_handler(ctx)
+    }
+
+    pub fn refresh_metadata(ctx: Context<RefreshMetadata>) -> Result<()> {
+        refresh_metadata_handler(ctx)
+    }
+
+    pub fn refresh_metadata_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefreshMetadataBatch<'info>>,
+    ) -> Result<()> {
+        refresh_metadata_batch_handler(ctx)
+    }
+
+    pub fn create_fee_vault(ctx: Context<CreateFeeVault>) -> Result<()> {
+        create_fee_vault_handler(ctx)
+    }
+
+    pub fn withdraw_fees<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawFees<'info>>,
+    ) -> Result<()> {
+        withdraw_fees_handler(ctx)
+    }
+
+    pub fn add_fee_waiver(ctx: Context<AddFeeWaiver>, input: FeeWaiverInput) -> Result<()> {
+        add_fee_waiver_handler(ctx, input)
+    }
+
+    pub fn remove_fee_waiver(ctx: Context<RemoveFeeWaiver>) -> Result<()> {
+        remove_fee_waiver_handler(ctx)
+    }
+
+    pub fn upgrade_global_state(
+        ctx: Context<UpgradeGlobalState>,
+        fee_distribution: Option<FeeDistribution>,
+    ) -> Result<()> {
+        upgrade_global_state_handler(ctx, fee_distribution)
+    }
+
+    pub fn upgrade_offset_tiers(
+        ctx: Context<UpgradeOffsetTiers>,
+        legacy_nfts: u64,
+    ) -> Result<()> {
+        upgrade_offset_tiers_handler(ctx, legacy_nfts)
     }
 
-    pub fn update_nft(ctx: Context<UpdateNft>, offset_amount: u64) -> Result<()> {
-        update_nft_handler(ctx, offset_amount)
+    pub fn upgrade_offset_metadata(ctx: Context<UpgradeOffsetMetadata>) -> Result<()> {
+        upgrade_offset_metadata_handler(ctx)
     }
 }
@@ -1,3 +1,16 @@
 pub const OFFSET_TIERS_SEED: &[u8] = b"offset_tiers";
 pub const OFFSET_METADATA_SEED: &[u8] = b"offset_metadata";
+pub const LEVEL_COUNTS_SEED: &[u8] = b"level_counts";
 pub const TOKEN_AUTHORITY_SEED: &[u8] = b"token_authority";
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
+pub const FEE_WAIVER_SEED: &[u8] = b"fee_waiver";
+
+/// The version seed of the offset tiers. Version 0 has none, so that it keeps
+/// the address of the offset tiers created before they were versioned
+pub fn offset_tiers_version_seed(version: u16) -> Vec<u8> {
+    if version == 0 {
+        vec![]
+    } else {
+        version.to_le_bytes().to_vec()
+    }
+}
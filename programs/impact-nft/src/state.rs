@@ -1,28 +1,65 @@
 use anchor_lang::prelude::*;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
 pub enum FeeType {
     Fixed,
     Percentage,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
 pub enum CoinType {
     Native,
     Spl,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+/// How the update fee scales when an NFT moves up several levels at once
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum LevelUpFeeScaling {
+    Flat,     // the fee is charged once per level-up
+    PerLevel, // the fee is charged for each level jumped
+}
+
+/// What update_nft does when an NFT's offset falls below its current level
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum DowngradePolicy {
+    Allow,       // the NFT moves down to the level of its offset
+    Forbid,      // the update fails
+    KeepHighest, // the NFT keeps the highest level it has reached
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
 pub struct FeeConfig {
     pub fee: u64, // if fee_type is Fixed, this is in lamports
     // if fee_type is Percentage, this is in basis points (100bp = 1%)
-    pub recipient: Pubkey, // either a token account or a SOL address
     pub fee_type: FeeType,
     pub coin_type: CoinType,
-    pub spl_token_mint: Option<Pubkey>, // if recipient is a token account, this is the mint
+    pub spl_token_mint: Option<Pubkey>, // if coin_type is Spl, this is the mint
+    // lower and upper bounds for percentage fees, ignored for fixed fees
+    pub min_fee: u64,
+    pub max_fee: Option<u64>,
 }
 impl FeeConfig {
-    pub const SPACE: usize = 8 + 32 + 1 + 1 + (1 + 32);
+    pub const SPACE: usize = 8 + 1 + 1 + (1 + 32) + 8 + (1 + 8);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeRecipient {
+    pub address: Pubkey, // a SOL address, token fees go to its associated token account
+    pub share: u16,      // share of the fees in basis points
+}
+impl FeeRecipient {
+    pub const SPACE: usize = 32 + 2;
+}
+
+/// How the fees collected in the fee vaults are split when they are withdrawn
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeDistribution {
+    pub recipients: Vec<FeeRecipient>, // shares must sum to 10_000
+    pub primary_recipient: u8,         // index of the recipient that receives any rounding dust
+}
+impl FeeDistribution {
+    pub const MAX_RECIPIENTS: usize = 5;
+    pub const SPACE: usize = (4 + FeeRecipient::SPACE * Self::MAX_RECIPIENTS) + 1;
 }
 
 /*
@@ -40,6 +77,27 @@ pub struct GlobalState {
     // number of levels, can probably be capped at u8 or u16
     pub levels: u16,
     pub fee: Option<FeeConfig>,
+    pub fee_distribution: Option<FeeDistribution>,
+    // charged when update_nft moves an nft up a level
+    pub update_fee: Option<FeeConfig>,
+    pub update_fee_scaling: LevelUpFeeScaling,
+    pub pending_fee_update: Option<PendingFeeUpdate>,
+    // the latest generation of offset tiers, new NFTs are minted against it
+    pub tiers_version: u16,
+    pub downgrade_policy: DowngradePolicy,
+}
+
+/// A change to the fees that takes effect at a future slot
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PendingFeeUpdate {
+    pub fee: Option<FeeConfig>,
+    pub update_fee: Option<FeeConfig>,
+    pub update_fee_scaling: LevelUpFeeScaling,
+    pub activation_slot: u64,
+}
+
+impl PendingFeeUpdate {
+    pub const SPACE: usize = (1 + FeeConfig::SPACE) + (1 + FeeConfig::SPACE) + 1 + 8;
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -47,6 +105,10 @@ pub struct GlobalStateCreateInput {
     pub admin_mint_authority: Pubkey,
     pub levels: u16,
     pub fee: Option<FeeConfig>,
+    pub fee_distribution: Option<FeeDistribution>,
+    pub update_fee: Option<FeeConfig>,
+    pub update_fee_scaling: LevelUpFeeScaling,
+    pub downgrade_policy: DowngradePolicy,
 }
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct GlobalStateUpdateInput {
@@ -54,10 +116,26 @@ pub struct GlobalStateUpdateInput {
     pub admin_mint_authority: Pubkey,
     pub levels: u16,
     pub fee: Option<FeeConfig>,
+    pub fee_distribution: Option<FeeDistribution>,
+    pub update_fee: Option<FeeConfig>,
+    pub update_fee_scaling: LevelUpFeeScaling,
+    // if set in the future, the fee changes are only applied from this slot
+    pub fee_activation_slot: Option<u64>,
+    pub downgrade_policy: DowngradePolicy,
 }
 
 impl GlobalState {
-    pub const SPACE: usize = 8 + 32 + 32 + 2 + 1 + FeeConfig::SPACE;
+    pub const SPACE: usize = 8
+        + 32
+        + 32
+        + 2
+        + (1 + FeeConfig::SPACE)
+        + (1 + FeeDistribution::SPACE)
+        + (1 + FeeConfig::SPACE)
+        + 1
+        + (1 + PendingFeeUpdate::SPACE)
+        + 2
+        + 1;
 
     pub fn set(
         &mut self,
@@ -65,28 +143,167 @@ impl GlobalState {
         admin_mint_authority: Pubkey,
         levels: u16,
         fee: Option<FeeConfig>,
+        fee_distribution: Option<FeeDistribution>,
+        update_fee: Option<FeeConfig>,
+        update_fee_scaling: LevelUpFeeScaling,
+        downgrade_policy: DowngradePolicy,
     ) {
         self.admin_update_authority = admin_update_authority;
         self.admin_mint_authority = admin_mint_authority;
         self.levels = levels;
         self.fee = fee;
+        self.fee_distribution = fee_distribution;
+        self.update_fee = update_fee;
+        self.update_fee_scaling = update_fee_scaling;
+        self.downgrade_policy = downgrade_policy;
+    }
+
+    fn activated_fee_update(&self, slot: u64) -> Option<&PendingFeeUpdate> {
+        self.pending_fee_update
+            .as_ref()
+            .filter(|pending| pending.activation_slot <= slot)
+    }
+
+    /// Applies the pending fee update once its activation slot is reached
+    pub fn activate_pending_fee_update(&mut self, slot: u64) {
+        match self.pending_fee_update.take() {
+            Some(pending) if pending.activation_slot <= slot => {
+                self.fee = pending.fee;
+                self.update_fee = pending.update_fee;
+                self.update_fee_scaling = pending.update_fee_scaling;
+            }
+            pending => self.pending_fee_update = pending,
+        }
+    }
+
+    /// The mint fee in effect at a slot, including a pending fee update that has activated
+    pub fn fee_at(&self, slot: u64) -> &Option<FeeConfig> {
+        match self.activated_fee_update(slot) {
+            Some(pending) => &pending.fee,
+            None => &self.fee,
+        }
+    }
+
+    /// The update fee and its scaling in effect at a slot,
+    /// including a pending fee update that has activated
+    pub fn update_fee_at(&self, slot: u64) -> (&Option<FeeConfig>, &LevelUpFeeScaling) {
+        match self.activated_fee_update(slot) {
+            Some(pending) => (&pending.update_fee, &pending.update_fee_scaling),
+            None => (&self.update_fee, &self.update_fee_scaling),
+        }
+    }
+}
+
+/// Collects the fees charged by the program for a global state.
+/// The SOL vault holds the fees as lamports in the vault account itself,
+/// a token vault holds them in the vault's associated token account for its mint.
+#[account]
+pub struct FeeVault {
+    pub global_state: Pubkey,
+    pub mint: Option<Pubkey>, // None for the SOL vault
+    // running totals, for reconciliation by the treasury
+    pub total_collected: u64,
+    pub total_withdrawn: u64,
+    pub bump: u8,
+}
+
+impl FeeVault {
+    pub const SPACE: usize = 8 + 32 + (1 + 32) + 8 + 8 + 1;
+
+    /// The SOL vault uses the default pubkey in place of a mint in its seeds
+    pub fn mint_seed(mint: Option<Pubkey>) -> Pubkey {
+        mint.unwrap_or_default()
+    }
+
+    pub fn set(&mut self, global_state: Pubkey, mint: Option<Pubkey>, bump: u8) {
+        self.global_state = global_state;
+        self.mint = mint;
+        self.bump = bump;
     }
 }
 
+/// Waives or discounts the fees for a wallet paying them,
+/// or for a program calling this one
+#[account]
+pub struct FeeWaiver {
+    pub global_state: Pubkey,
+    pub key: Pubkey,   // the wallet or calling program the waiver applies to
+    pub discount: u16, // in basis points, 10_000 waives the fee entirely
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeWaiverInput {
+    pub key: Pubkey,
+    pub discount: u16,
+}
+
+impl FeeWaiver {
+    pub const SPACE: usize = 8 + 32 + 32 + 2 + 1;
+
+    pub fn set(&mut self, global_state: Pubkey, input: FeeWaiverInput, bump: u8) {
+        self.global_state = global_state;
+        self.key = input.key;
+        self.discount = input.discount;
+        self.bump = bump;
+    }
+}
+
+/// A creator listed in the metadata of NFTs at a level.
+/// The token authority is always added as a verified creator, with no share unless listed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct LevelCreator {
+    pub address: Pubkey,
+    pub share: u8, // percentage, the shares of a level's creators must sum to 100
+}
+
+impl LevelCreator {
+    /// Metaplex allows 5 creators, one is kept for the token authority
+    pub const MAX_CREATORS: usize = 4;
+    pub const SPACE: usize = 32 + 1;
+}
+
 /**
  * The Level struct is used to store the offset tiers.
  */
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
 pub struct Level {
     pub offset: u64,
-    pub uri: String,    //mplx limit of 200
+    // the stake principal required in addition to the offset
+    pub min_principal: u64,
+    // seconds an NFT must have spent at the previous level before it is promoted to this one
+    pub min_duration_at_previous_level: u64,
+    // mplx limit of 200, may contain {offset}, {level}, {mint} and {owner} placeholders,
+    // the expanded uri must also fit within the limit
+    pub uri: String,
     pub name: String,   //mplx limit of 32
     pub symbol: String, //mplx limit of 10
     pub collection_mint: Pubkey,
+    // overrides the global fee for minting into or upgrading to this level
+    pub fee: Option<FeeConfig>,
+    // secondary sale royalties of NFTs at this level, defaults to none
+    pub royalty_basis_points: Option<u16>,
+    // if empty, the token authority is the sole creator
+    pub creators: Vec<LevelCreator>,
 }
 
 impl Level {
-    pub const SPACE: usize = 8 + (4 + 200) + (4 + 32) + (4 + 10) + 15 + 32;
+    pub const SPACE: usize = 8
+        + 8
+        + 8
+        + (4 + 200)
+        + (4 + 32)
+        + (4 + 10)
+        + 15
+        + 32
+        + (1 + FeeConfig::SPACE)
+        + (1 + 2)
+        + (4 + LevelCreator::SPACE * LevelCreator::MAX_CREATORS);
+
+    /// The fee schedule for this level, falling back to the given global fee config
+    pub fn fee_config<'a>(&'a self, default: &'a Option<FeeConfig>) -> Option<&'a FeeConfig> {
+        self.fee.as_ref().or(default.as_ref())
+    }
 }
 
 #[account]
@@ -94,6 +311,25 @@ impl Level {
 pub struct OffsetTiers {
     pub levels: Vec<Level>,
     pub bump: u8,
+    pub version: u16,
+}
+
+/// The number of NFTs currently at each level of a version of the offset tiers,
+/// indexed like its levels. Kept apart from the offset tiers, so that minting and
+/// updating NFTs does not lock and rewrite the level definitions
+#[account]
+#[derive(Debug)]
+pub struct LevelCounts {
+    pub counts: Vec<u64>,
+    pub bump: u8,
+    // legacy NFTs still to be upgraded onto version 0, and counted at their levels
+    pub pending_legacy_upgrades: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct NftUpdateInput {
+    pub offset_amount: u64,
+    pub principal: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -102,70 +338,230 @@ pub struct OffsetTiersInput {
 }
 
 impl OffsetTiers {
-    pub const MAX_LEVELS: usize = 10;
-    /** Allocate up to 10 levels (can be modified) */
-    pub const SPACE: usize = 4   // vec
-        + (Level::SPACE * Self::MAX_LEVELS)
-        + 1                      // bump
-        + 8; // discriminator
+    /** The account is reallocated as levels are added or removed */
+    pub fn space(levels: usize) -> usize {
+        4   // vec
+        + (Level::SPACE * levels)
+        + 1 // bump
+        + 2 // version
+        + 8 // discriminator
+    }
 
     pub fn set(&mut self, input: OffsetTiersInput) {
         self.levels = input.levels;
     }
 
-    pub fn get_level(&self, offset: u64) -> Option<&Level> {
-        // Defaults to zero if level doesn't exist for offset.
-        let level_index = self.get_index_from_offset(offset).unwrap_or(0);
-
-        msg!(
-            "Level for offset {} is {} (starts at {})",
-            offset,
-            level_index,
-            self.levels[level_index].offset
-        );
-
-        if level_index < self.levels.len() - 1 {
-            msg!(
-                "Next level at offset {}",
-                self.levels[level_index + 1].offset
-            );
-        } else {
-            msg!("This is the max level");
+    /// Promotes an NFT level by level, as far as the time spent at the previous level allows.
+    /// Levels skipped within a single promotion have no time spent at them
+    pub fn cap_promotion(
+        &self,
+        current_level_index: usize,
+        target_level_index: usize,
+        time_at_current_level: u64,
+    ) -> usize {
+        if target_level_index <= current_level_index {
+            return target_level_index;
         }
 
-        Some(&self.levels[level_index])
+        let mut reached_level_index = current_level_index;
+        for index in (current_level_index + 1)..=target_level_index {
+            let time_at_previous_level = if index == current_level_index + 1 {
+                time_at_current_level
+            } else {
+                0
+            };
+            if time_at_previous_level < self.levels[index].min_duration_at_previous_level {
+                msg!("Level {} requires more time at the previous level", index);
+                break;
+            }
+            reached_level_index = index;
+        }
+        reached_level_index
     }
 
-    pub fn get_index_from_offset(&self, offset: u64) -> Option<usize> {
+    /// The highest level whose offset and principal thresholds are both met
+    pub fn get_index(&self, offset: u64, principal: u64) -> Option<usize> {
         self.levels
             .iter()
             .rev()
-            .position(|level| level.offset <= offset)
+            .position(|level| level.offset <= offset && level.min_principal <= principal)
             .map(|i| self.levels.len() - 1 - i)
     }
 }
 
+impl LevelCounts {
+    /** The account is reallocated with the offset tiers as levels are added or removed */
+    pub fn space(levels: usize) -> usize {
+        4 + (8 * levels) // counts
+        + 1 // bump
+        + 8 // pending_legacy_upgrades
+        + 8 // discriminator
+    }
+
+    pub fn set(&mut self, levels: usize, bump: u8) {
+        self.resize(levels);
+        self.bump = bump;
+    }
+
+    pub fn resize(&mut self, levels: usize) {
+        self.counts.resize(levels, 0);
+    }
+
+    pub fn count(&self, index: usize) -> u64 {
+        self.counts.get(index).copied().unwrap_or(0)
+    }
+
+    /// The number of NFTs at the given level or any level above it.
+    /// Levels holding NFTs cannot be removed or shifted, as the NFTs' offset metadata
+    /// refers to their level by index
+    pub fn nfts_from(&self, index: usize) -> u64 {
+        self.counts.iter().skip(index).sum()
+    }
+
+    /// Moves an NFT between levels, or places a newly minted NFT if there is no previous level
+    pub fn record_level_change(&mut self, previous_index: Option<usize>, new_index: usize) {
+        if let Some(previous_index) = previous_index {
+            self.remove_nft(previous_index);
+        }
+        self.add_nft(new_index);
+    }
+
+    pub fn add_nft(&mut self, index: usize) {
+        self.counts[index] += 1;
+    }
+
+    pub fn remove_nft(&mut self, index: usize) {
+        self.counts[index] = self.counts[index].saturating_sub(1);
+    }
+}
+
 #[account]
 pub struct OffsetMetadata {
     pub current_level_index: u16,
     pub offset: u64,
     pub bump: u8,
+    pub principal: u64,
+    // the offset tiers version that current_level_index refers to
+    pub tiers_version: u16,
+    pub highest_level_index: u16,
+    // unix timestamps
+    pub minted_at: i64,
+    pub level_reached_at: i64,
+    pub last_updated: i64,
+    // the number of update_nft calls since the nft was minted
+    pub update_count: u64,
 }
 
 impl OffsetMetadata {
-    pub const SPACE: usize = 8 + 2 + 8 + 1;
+    pub const SPACE: usize = 8 + 2 + 8 + 1 + 8 + 2 + 2 + 8 + 8 + 8 + 8;
 
     pub fn set_amount(&mut self, offset: u64) {
         self.offset = offset;
     }
 
+    pub fn set_principal(&mut self, principal: u64) {
+        self.principal = principal;
+    }
+
     pub fn set_level_index(&mut self, index: usize) {
         self.current_level_index = index as u16;
+        self.highest_level_index = self.highest_level_index.max(self.current_level_index);
     }
 
-    pub fn set(&mut self, offset: u64, bump: u8, level_index: usize) {
+    /// Places the NFT at a level of a different offset tiers version,
+    /// where the highest level reached in the previous version has no meaning
+    pub fn reset_level_index(&mut self, index: usize) {
+        self.current_level_index = index as u16;
+        self.highest_level_index = self.current_level_index;
+    }
+
+    pub fn set_level_reached_at(&mut self, timestamp: i64) {
+        self.level_reached_at = timestamp;
+    }
+
+    pub fn set_tiers_version(&mut self, version: u16) {
+        self.tiers_version = version;
+    }
+
+    /// Records the latest offset and principal, whether or not the level changes
+    pub fn record_update(&mut self, offset: u64, principal: u64, timestamp: i64) {
+        self.set_amount(offset);
+        self.set_principal(principal);
+        self.last_updated = timestamp;
+        self.update_count = self.update_count.saturating_add(1);
+    }
+
+    pub fn set(&mut self, offset: u64, bump: u8, level_index: usize, timestamp: i64) {
         self.set_amount(offset);
         self.bump = bump;
         self.set_level_index(level_index);
+        self.minted_at = timestamp;
+        self.set_level_reached_at(timestamp);
+        self.last_updated = timestamp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // levels at offsets 0, 100, 200, ... each with its own thresholds
+    fn offset_tiers(thresholds: &[(u64, u64)]) -> OffsetTiers {
+        OffsetTiers {
+            levels: thresholds
+                .iter()
+                .enumerate()
+                .map(|(index, &(min_principal, min_duration_at_previous_level))| Level {
+                    offset: index as u64 * 100,
+                    min_principal,
+                    min_duration_at_previous_level,
+                    uri: String::new(),
+                    name: String::new(),
+                    symbol: String::new(),
+                    collection_mint: Pubkey::new_unique(),
+                    fee: None,
+                    royalty_basis_points: None,
+                    creators: vec![],
+                })
+                .collect(),
+            bump: 0,
+            version: 0,
+        }
+    }
+
+    #[test]
+    fn get_index_requires_both_thresholds() {
+        let tiers = offset_tiers(&[(0, 0), (0, 0), (1_000, 0)]);
+        assert_eq!(tiers.get_index(250, 1_000), Some(2));
+        // the offset qualifies for the third level, but the principal does not
+        assert_eq!(tiers.get_index(250, 999), Some(1));
+        assert_eq!(tiers.get_index(50, 0), Some(0));
+    }
+
+    #[test]
+    fn cap_promotion_caps_a_jump_at_the_first_level_requiring_more_time() {
+        let tiers = offset_tiers(&[(0, 0), (0, 60), (0, 0), (0, 60), (0, 0)]);
+        // the time at the current level counts towards the next level only,
+        // so the jump stops below the next gated level
+        assert_eq!(tiers.cap_promotion(0, 4, 60), 2);
+    }
+
+    #[test]
+    fn cap_promotion_blocks_a_jump_at_the_first_gated_level() {
+        let tiers = offset_tiers(&[(0, 0), (0, 60), (0, 0)]);
+        assert_eq!(tiers.cap_promotion(0, 2, 59), 0);
+        // demotions and unchanged levels are never capped
+        assert_eq!(tiers.cap_promotion(2, 0, 0), 0);
+        assert_eq!(tiers.cap_promotion(1, 1, 0), 1);
+    }
+
+    #[test]
+    fn cap_promotion_allows_a_later_promotion_once_enough_time_has_passed() {
+        let tiers = offset_tiers(&[(0, 0), (0, 60), (0, 60)]);
+        let reached = tiers.cap_promotion(0, 2, 60);
+        assert_eq!(reached, 1);
+        // the level was just reached, so no time has been spent at it yet
+        assert_eq!(tiers.cap_promotion(reached, 2, 0), 1);
+        assert_eq!(tiers.cap_promotion(reached, 2, 60), 2);
     }
 }
@@ -1,49 +1,613 @@
-use crate::state::{CoinType, FeeConfig, FeeType};
+use crate::error::ErrorCode;
+use crate::seeds::FEE_VAULT_SEED;
+use crate::state::{CoinType, FeeConfig, FeeDistribution, FeeType, FeeVault, FeeWaiver};
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::program::invoke;
 use anchor_lang::solana_program::system_instruction;
-use anchor_spl::token::{self, Token};
+use anchor_lang::solana_program::sysvar::instructions::get_instruction_relative;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token_interface::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_interface::spl_token_2022::extension::{
+    BaseStateWithExtensions, StateWithExtensions,
+};
+use anchor_spl::token_interface::spl_token_2022::state::Mint as MintState;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
 
-#[allow(dead_code)]
-pub fn handle_fees<'a>(
-    token_program: &Program<'a, Token>,
+const BASIS_POINTS: u128 = 10_000;
+
+/// Checks that the recipient shares of a fee distribution add up and
+/// that the primary recipient exists
+pub fn validate_fee_distribution(fee_distribution: &FeeDistribution) -> Result<()> {
+    let recipients = &fee_distribution.recipients;
+    require!(
+        !recipients.is_empty() && recipients.len() <= FeeDistribution::MAX_RECIPIENTS,
+        ErrorCode::InvalidFeeDistribution
+    );
+    require!(
+        (fee_distribution.primary_recipient as usize) < recipients.len(),
+        ErrorCode::InvalidFeeDistribution
+    );
+    let total_shares = recipients
+        .iter()
+        .map(|recipient| recipient.share as u128)
+        .sum::<u128>();
+    require!(
+        total_shares == BASIS_POINTS,
+        ErrorCode::InvalidFeeDistribution
+    );
+    Ok(())
+}
+
+/// Checks that a fee config is consistent: percentage fees are at most 10,000 basis points,
+/// the min and max fee bounds are ordered, and SPL fees, and only SPL fees, have a mint
+pub fn validate_fee_config(fee_config: &FeeConfig) -> Result<()> {
+    if fee_config.fee_type == FeeType::Percentage {
+        require_gte!(
+            BASIS_POINTS,
+            fee_config.fee as u128,
+            ErrorCode::InvalidFeeBasisPoints
+        );
+    }
+    if let Some(max_fee) = fee_config.max_fee {
+        require_gte!(max_fee, fee_config.min_fee, ErrorCode::InvalidFeeConfig);
+    }
+    match fee_config.coin_type {
+        CoinType::Native => require!(
+            fee_config.spl_token_mint.is_none(),
+            ErrorCode::InvalidFeeMint
+        ),
+        CoinType::Spl => require!(
+            fee_config.spl_token_mint.is_some(),
+            ErrorCode::InvalidFeeMint
+        ),
+    }
+    Ok(())
+}
+
+/// Validates the fee configs and the fee distribution of a global state.
+/// Any configured fee requires a fee distribution, so that collected fees can be withdrawn.
+pub fn validate_fees(
+    fee_configs: &[&Option<FeeConfig>],
+    fee_distribution: &Option<FeeDistribution>,
+) -> Result<()> {
+    for fee_config in fee_configs.iter().filter_map(|fee_config| fee_config.as_ref()) {
+        validate_fee_config(fee_config)?;
+        require!(fee_distribution.is_some(), ErrorCode::NoFeeDistribution);
+    }
+    if let Some(fee_distribution) = fee_distribution {
+        validate_fee_distribution(fee_distribution)?;
+    }
+    Ok(())
+}
+
+/// Checks the fee recipient accounts passed to an instruction against the fee distribution,
+/// and returns them in the order of the configured recipients.
+/// The recipient accounts are expected at the start of the remaining accounts.
+/// For the SOL vault these are the recipient addresses themselves, for a token vault
+/// they are the recipients' associated token accounts for the vault mint.
+pub fn get_fee_recipients<'c, 'info>(
+    fee_distribution: &FeeDistribution,
+    mint: Option<(&Pubkey, &Pubkey)>, // the vault mint and its token program
+    remaining_accounts: &'c [AccountInfo<'info>],
+) -> Result<&'c [AccountInfo<'info>]> {
+    let recipients = remaining_accounts
+        .get(..fee_distribution.recipients.len())
+        .ok_or(ErrorCode::InvalidFeeRecipient)?;
+    for (recipient, account) in fee_distribution.recipients.iter().zip(recipients) {
+        let expected_address = match mint {
+            Some((mint, token_program)) => get_associated_token_address_with_program_id(
+                &recipient.address,
+                mint,
+                token_program,
+            ),
+            None => recipient.address,
+        };
+        require_keys_eq!(
+            expected_address,
+            account.key(),
+            ErrorCode::InvalidFeeRecipient
+        );
+        require!(account.is_writable, ErrorCode::InvalidFeeRecipient);
+    }
+    Ok(recipients)
+}
+
+/// Splits an amount between the recipients of a fee distribution according to their shares.
+/// Each share is rounded down, and the remaining dust goes to the primary recipient.
+pub fn split_fee(fee_distribution: &FeeDistribution, amount: u64) -> Result<Vec<u64>> {
+    validate_fee_distribution(fee_distribution)?;
+
+    let mut amounts = fee_distribution
+        .recipients
+        .iter()
+        .map(|recipient| {
+            // cannot overflow u64 as share <= BASIS_POINTS
+            ((amount as u128) * (recipient.share as u128) / BASIS_POINTS) as u64
+        })
+        .collect::<Vec<u64>>();
+
+    let dust = amount - amounts.iter().sum::<u64>();
+    amounts[fee_distribution.primary_recipient as usize] += dust;
+
+    Ok(amounts)
+}
+
+/// Calculates the fee owed for a principal.
+/// Percentage fees are calculated in u128 fixed-point and rounded down,
+/// so that the result is reproducible off-chain and never overcharges the payer.
+/// The result is then clamped to the min_fee and max_fee bounds of the fee config.
+pub fn calculate_fee(fee_config: &FeeConfig, principal: u64) -> Result<u64> {
+    match fee_config.fee_type {
+        FeeType::Fixed => Ok(fee_config.fee),
+        FeeType::Percentage => {
+            let amount = (principal as u128)
+                .checked_mul(fee_config.fee as u128)
+                .ok_or(ErrorCode::FeeCalculationOverflow)?
+                / BASIS_POINTS;
+            let amount = u64::try_from(amount).map_err(|_| ErrorCode::FeeCalculationOverflow)?;
+
+            let max_fee = fee_config.max_fee.unwrap_or(u64::MAX);
+            require_gte!(max_fee, fee_config.min_fee, ErrorCode::InvalidFeeConfig);
+            Ok(amount.clamp(fee_config.min_fee, max_fee))
+        }
+    }
+}
+
+/// Returns the discount in basis points that a fee waiver grants.
+/// A waiver applies if it is keyed by the fee payer, or by the program calling this one,
+/// which is read from the instructions sysvar.
+pub fn get_fee_discount(
+    fee_waiver: Option<&Account<FeeWaiver>>,
+    fee_payer: &Pubkey,
+    instructions_sysvar: Option<&AccountInfo>,
+) -> Result<u16> {
+    let Some(fee_waiver) = fee_waiver else {
+        return Ok(0);
+    };
+    if fee_waiver.key != *fee_payer {
+        let instructions_sysvar = instructions_sysvar.ok_or(ErrorCode::InvalidFeeWaiver)?;
+        let calling_program = get_instruction_relative(0, instructions_sysvar)?.program_id;
+        require!(
+            calling_program != crate::ID && fee_waiver.key == calling_program,
+            ErrorCode::InvalidFeeWaiver
+        );
+    }
+    msg!("Applying fee waiver for {}", fee_waiver.key);
+    Ok(fee_waiver.discount)
+}
+
+/// Applies a discount in basis points to a fee, rounding the discounted fee down
+pub fn apply_discount(amount: u64, discount: u16) -> Result<u64> {
+    let remaining_share = BASIS_POINTS
+        .checked_sub(discount as u128)
+        .ok_or(ErrorCode::InvalidFeeWaiver)?;
+    // cannot overflow u64 as remaining_share <= BASIS_POINTS
+    Ok(((amount as u128) * remaining_share / BASIS_POINTS) as u64)
+}
+
+/// Returns the amount that must be sent so that the recipient receives `amount`
+/// after any Token-2022 transfer fee is withheld by the mint.
+fn gross_up_for_transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    let mint_data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<MintState>::unpack(&mint_data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => {
+            let transfer_fee = transfer_fee_config
+                .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+                .ok_or(ErrorCode::FeeCalculationOverflow)?;
+            Ok(amount
+                .checked_add(transfer_fee)
+                .ok_or(ErrorCode::FeeCalculationOverflow)?)
+        }
+        Err(_) => Ok(amount),
+    }
+}
+
+/// Checks the SPL fee accounts against the vault mint, and returns them
+/// as (token program, mint, vault token account)
+pub fn get_spl_fee_accounts<'b, 'a>(
+    fee_vault: &FeeVault,
+    fee_vault_key: &Pubkey,
+    fee_token_program: Option<&'b Interface<'a, TokenInterface>>,
+    fee_mint: Option<&'b InterfaceAccount<'a, Mint>>,
+    fee_vault_token_account: Option<&'b InterfaceAccount<'a, TokenAccount>>,
+) -> Result<(
+    &'b Interface<'a, TokenInterface>,
+    &'b InterfaceAccount<'a, Mint>,
+    &'b InterfaceAccount<'a, TokenAccount>,
+)> {
+    let vault_mint = fee_vault.mint.ok_or(ErrorCode::InvalidFeeVault)?;
+    let fee_token_program = fee_token_program.ok_or(ErrorCode::InvalidFeeMint)?;
+    let fee_mint = fee_mint.ok_or(ErrorCode::InvalidFeeMint)?;
+    let fee_vault_token_account = fee_vault_token_account.ok_or(ErrorCode::InvalidFeeVault)?;
+    require_keys_eq!(fee_mint.key(), vault_mint, ErrorCode::InvalidFeeMint);
+    require_keys_eq!(
+        *fee_mint.to_account_info().owner,
+        fee_token_program.key(),
+        ErrorCode::InvalidFeeMint
+    );
+    require_keys_eq!(
+        fee_vault_token_account.key(),
+        get_associated_token_address_with_program_id(
+            fee_vault_key,
+            &vault_mint,
+            &fee_token_program.key()
+        ),
+        ErrorCode::InvalidFeeVault
+    );
+    Ok((fee_token_program, fee_mint, fee_vault_token_account))
+}
+
+/// The accounts an instruction needs to charge a fee.
+/// SPL fees require the fee mint, the token program that owns it (SPL Token or Token-2022),
+/// the payer's token account for that mint and the vault's token account.
+pub struct FeeAccounts<'b, 'a> {
+    pub fee_payer: &'b Signer<'a>,
+    pub fee_vault: Option<&'b mut Account<'a, FeeVault>>,
+    pub fee_vault_token_account: Option<&'b InterfaceAccount<'a, TokenAccount>>,
+    pub fee_token_program: Option<&'b Interface<'a, TokenInterface>>,
+    pub fee_mint: Option<&'b InterfaceAccount<'a, Mint>>,
+    pub fee_payer_token_account: Option<&'b InterfaceAccount<'a, TokenAccount>>,
+}
+
+/// Transfers a fee from the fee payer to the fee vault.
+/// Fails if the amount leaving the payer, including any Token-2022 transfer fee,
+/// is higher than the max_fee_payable the payer accepts
+pub fn handle_fees(
     fee_config: &FeeConfig,
-    fee_payer: &Signer<'a>,
-    fee_payer_token_account: Option<AccountInfo<'a>>,
-    recipient: &AccountInfo<'a>, // either a token account or a sol recipient account
-    principal: u64,
+    accounts: FeeAccounts,
+    amount: u64,
+    max_fee_payable: u64,
 ) -> Result<()> {
+    if amount == 0 {
+        // e.g. levels configured to be free
+        return Ok(());
+    }
     msg!("Handling fees");
 
-    let amount: u64 = match fee_config.fee_type {
-        FeeType::Fixed => fee_config.fee,
-        FeeType::Percentage => (principal as f64 * fee_config.fee as f64 / 10_000.0) as u64,
-    };
+    let fee_payer = accounts.fee_payer;
+    let fee_vault = accounts.fee_vault.ok_or(ErrorCode::InvalidFeeVault)?;
+    require!(
+        fee_vault.mint == fee_config.spl_token_mint,
+        ErrorCode::InvalidFeeVault
+    );
 
     match fee_config.coin_type {
         CoinType::Native => {
-            msg!("Transferring {} lamports from {}", amount, fee_payer.key());
-            let ix = system_instruction::transfer(&fee_payer.key(), &recipient.key(), amount);
-            invoke(&ix, &[fee_payer.to_account_info(), recipient.clone()])?;
+            require_gte!(max_fee_payable, amount, ErrorCode::FeeExceedsMaximum);
+            msg!(
+                "Transferring {} lamports from {} to the fee vault",
+                amount,
+                fee_payer.key()
+            );
+            let ix = system_instruction::transfer(&fee_payer.key(), &fee_vault.key(), amount);
+            invoke(
+                &ix,
+                &[fee_payer.to_account_info(), fee_vault.to_account_info()],
+            )?;
         }
         CoinType::Spl => {
+            let (fee_token_program, fee_mint, fee_vault_token_account) = get_spl_fee_accounts(
+                fee_vault,
+                &fee_vault.key(),
+                accounts.fee_token_program,
+                accounts.fee_mint,
+                accounts.fee_vault_token_account,
+            )?;
+            let fee_payer_token_account = accounts
+                .fee_payer_token_account
+                .ok_or(ErrorCode::InvalidFeePayerTokenAccount)?;
+            require_keys_eq!(
+                fee_payer_token_account.mint,
+                fee_mint.key(),
+                ErrorCode::InvalidFeePayerTokenAccount
+            );
+
+            let transfer_amount = gross_up_for_transfer_fee(&fee_mint.to_account_info(), amount)?;
+            require_gte!(max_fee_payable, transfer_amount, ErrorCode::FeeExceedsMaximum);
             msg!(
-                "Transferring {} in SPL-tokens from {}",
-                amount,
+                "Transferring {} in SPL-tokens from {} to the fee vault",
+                transfer_amount,
                 fee_payer.key()
             );
-            token::transfer(
+            transfer_checked(
                 CpiContext::new(
-                    token_program.to_account_info(),
-                    token::Transfer {
-                        from: fee_payer_token_account.unwrap().clone(),
-                        to: recipient.clone(),
+                    fee_token_program.to_account_info(),
+                    TransferChecked {
+                        from: fee_payer_token_account.to_account_info(),
+                        mint: fee_mint.to_account_info(),
+                        to: fee_vault_token_account.to_account_info(),
                         authority: fee_payer.to_account_info(),
                     },
                 ),
-                1,
+                transfer_amount,
+                fee_mint.decimals,
             )?;
         }
     }
+
+    fee_vault.total_collected = fee_vault
+        .total_collected
+        .checked_add(amount)
+        .ok_or(ErrorCode::FeeCalculationOverflow)?;
     Ok(())
 }
+
+/// Sweeps the balance of a fee vault to the fee recipients, split according to the fee distribution.
+/// The SOL vault keeps enough lamports to remain rent-exempt.
+pub fn withdraw_from_vault<'a>(
+    fee_distribution: &FeeDistribution,
+    fee_vault: &mut Account<'a, FeeVault>,
+    fee_vault_token_account: Option<&InterfaceAccount<'a, TokenAccount>>,
+    fee_token_program: Option<&Interface<'a, TokenInterface>>,
+    fee_mint: Option<&InterfaceAccount<'a, Mint>>,
+    remaining_accounts: &[AccountInfo<'a>],
+) -> Result<()> {
+    let fee_vault_key = fee_vault.key();
+    let amount = match fee_vault.mint {
+        None => {
+            let recipients = get_fee_recipients(fee_distribution, None, remaining_accounts)?;
+            let rent_exempt_balance =
+                Rent::get()?.minimum_balance(fee_vault.to_account_info().data_len());
+            let amount = fee_vault.get_lamports().saturating_sub(rent_exempt_balance);
+            let amounts = split_fee(fee_distribution, amount)?;
+
+            for (recipient, amount) in recipients.iter().zip(amounts) {
+                msg!("Withdrawing {} lamports to {}", amount, recipient.key());
+                fee_vault.sub_lamports(amount)?;
+                recipient.add_lamports(amount)?;
+            }
+            amount
+        }
+        Some(vault_mint) => {
+            let (fee_token_program, fee_mint, fee_vault_token_account) = get_spl_fee_accounts(
+                fee_vault,
+                &fee_vault_key,
+                fee_token_program,
+                fee_mint,
+                fee_vault_token_account,
+            )?;
+            let recipients = get_fee_recipients(
+                fee_distribution,
+                Some((&vault_mint, &fee_token_program.key())),
+                remaining_accounts,
+            )?;
+            let amount = fee_vault_token_account.amount;
+            let amounts = split_fee(fee_distribution, amount)?;
+
+            let global_state = fee_vault.global_state;
+            let seeds = [
+                FEE_VAULT_SEED,
+                global_state.as_ref(),
+                vault_mint.as_ref(),
+                &[fee_vault.bump],
+            ];
+            for (recipient, amount) in recipients.iter().zip(amounts) {
+                if amount == 0 {
+                    continue;
+                }
+                msg!("Withdrawing {} in SPL-tokens to {}", amount, recipient.key());
+                transfer_checked(
+                    CpiContext::new(
+                        fee_token_program.to_account_info(),
+                        TransferChecked {
+                            from: fee_vault_token_account.to_account_info(),
+                            mint: fee_mint.to_account_info(),
+                            to: recipient.clone(),
+                            authority: fee_vault.to_account_info(),
+                        },
+                    )
+                    .with_signer(&[&seeds]),
+                    amount,
+                    fee_mint.decimals,
+                )?;
+            }
+            amount
+        }
+    };
+
+    fee_vault.total_withdrawn = fee_vault
+        .total_withdrawn
+        .checked_add(amount)
+        .ok_or(ErrorCode::FeeCalculationOverflow)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FeeRecipient;
+    use anchor_lang::solana_program::sysvar;
+    use anchor_lang::solana_program::sysvar::instructions::{
+        construct_instructions_data, BorrowedInstruction,
+    };
+
+    fn percentage_fee(fee: u64, min_fee: u64, max_fee: Option<u64>) -> FeeConfig {
+        FeeConfig {
+            fee,
+            fee_type: FeeType::Percentage,
+            coin_type: CoinType::Native,
+            spl_token_mint: None,
+            min_fee,
+            max_fee,
+        }
+    }
+
+    // the discount of a 25% waiver keyed by waiver_key, in a transaction whose
+    // top-level instruction calls top_level_program, if the instructions sysvar is passed
+    fn waiver_discount(
+        waiver_key: Pubkey,
+        fee_payer: Pubkey,
+        top_level_program: Option<Pubkey>,
+    ) -> Result<u16> {
+        let fee_waiver = FeeWaiver {
+            global_state: Pubkey::new_unique(),
+            key: waiver_key,
+            discount: 2_500,
+            bump: 0,
+        };
+        let mut waiver_data = vec![];
+        fee_waiver.try_serialize(&mut waiver_data)?;
+        let (waiver_address, mut waiver_lamports) = (Pubkey::new_unique(), 0);
+        let waiver_info = AccountInfo::new(
+            &waiver_address,
+            false,
+            false,
+            &mut waiver_lamports,
+            &mut waiver_data,
+            &crate::ID,
+            false,
+            0,
+        );
+        let fee_waiver = Account::<FeeWaiver>::try_from(&waiver_info)?;
+
+        let mut sysvar_data = top_level_program
+            .map(|program_id| {
+                construct_instructions_data(&[BorrowedInstruction {
+                    program_id: &program_id,
+                    accounts: vec![],
+                    data: &[],
+                }])
+            })
+            .unwrap_or_default();
+        let mut sysvar_lamports = 0;
+        let sysvar_info = AccountInfo::new(
+            &sysvar::instructions::ID,
+            false,
+            false,
+            &mut sysvar_lamports,
+            &mut sysvar_data,
+            &sysvar::ID,
+            false,
+            0,
+        );
+        get_fee_discount(
+            Some(&fee_waiver),
+            &fee_payer,
+            top_level_program.map(|_| &sysvar_info),
+        )
+    }
+
+    fn distribution(shares: &[u16], primary_recipient: u8) -> FeeDistribution {
+        FeeDistribution {
+            recipients: shares
+                .iter()
+                .map(|&share| FeeRecipient {
+                    address: Pubkey::new_unique(),
+                    share,
+                })
+                .collect(),
+            primary_recipient,
+        }
+    }
+
+    #[test]
+    fn percentage_fees_round_down() {
+        let fee_config = percentage_fee(100, 0, None); // 1%
+        assert_eq!(calculate_fee(&fee_config, 100).unwrap(), 1);
+        assert_eq!(calculate_fee(&fee_config, 199).unwrap(), 1);
+        assert_eq!(calculate_fee(&fee_config, 99).unwrap(), 0);
+    }
+
+    #[test]
+    fn percentage_fees_on_large_principals() {
+        let full = percentage_fee(10_000, 0, None);
+        assert_eq!(calculate_fee(&full, u64::MAX).unwrap(), u64::MAX);
+
+        let half = percentage_fee(5_000, 0, None);
+        assert_eq!(calculate_fee(&half, u64::MAX).unwrap(), u64::MAX / 2);
+
+        // only reachable with an unvalidated config, but must not wrap
+        let double = percentage_fee(20_000, 0, None);
+        assert_eq!(
+            calculate_fee(&double, u64::MAX).unwrap_err(),
+            ErrorCode::FeeCalculationOverflow.into()
+        );
+    }
+
+    #[test]
+    fn percentage_fees_are_clamped() {
+        let fee_config = percentage_fee(100, 5, Some(50));
+        assert_eq!(calculate_fee(&fee_config, 0).unwrap(), 5);
+        assert_eq!(calculate_fee(&fee_config, 1_000).unwrap(), 10);
+        assert_eq!(calculate_fee(&fee_config, u64::MAX).unwrap(), 50);
+
+        let inverted = percentage_fee(100, 50, Some(5));
+        assert_eq!(
+            calculate_fee(&inverted, 1_000).unwrap_err(),
+            ErrorCode::InvalidFeeConfig.into()
+        );
+    }
+
+    #[test]
+    fn fixed_fees_ignore_principal_and_bounds() {
+        let fee_config = FeeConfig {
+            fee_type: FeeType::Fixed,
+            ..percentage_fee(1_000, 5_000, Some(10))
+        };
+        assert_eq!(calculate_fee(&fee_config, 0).unwrap(), 1_000);
+        assert_eq!(calculate_fee(&fee_config, u64::MAX).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn split_fee_assigns_dust_to_the_primary_recipient() {
+        let fee_distribution = distribution(&[3_333, 3_333, 3_334], 1);
+        assert_eq!(split_fee(&fee_distribution, 10).unwrap(), vec![3, 4, 3]);
+        assert_eq!(split_fee(&fee_distribution, 0).unwrap(), vec![0, 0, 0]);
+
+        let amounts = split_fee(&fee_distribution, u64::MAX).unwrap();
+        assert_eq!(amounts.iter().map(|&a| a as u128).sum::<u128>(), u64::MAX as u128);
+    }
+
+    #[test]
+    fn split_fee_rejects_invalid_distributions() {
+        assert_eq!(
+            split_fee(&distribution(&[5_000, 4_999], 0), 100).unwrap_err(),
+            ErrorCode::InvalidFeeDistribution.into()
+        );
+        assert_eq!(
+            split_fee(&distribution(&[5_000, 5_000], 2), 100).unwrap_err(),
+            ErrorCode::InvalidFeeDistribution.into()
+        );
+    }
+
+    #[test]
+    fn fee_waivers_apply_to_the_payer_or_the_calling_program() {
+        let payer = Pubkey::new_unique();
+        assert_eq!(waiver_discount(payer, payer, None).unwrap(), 2_500);
+
+        let calling_program = Pubkey::new_unique();
+        assert_eq!(
+            waiver_discount(calling_program, payer, Some(calling_program)).unwrap(),
+            2_500
+        );
+        // a program waiver requires the instructions sysvar to identify the caller
+        assert_eq!(
+            waiver_discount(calling_program, payer, None).unwrap_err(),
+            ErrorCode::InvalidFeeWaiver.into()
+        );
+        // called directly rather than by the program the waiver is keyed by
+        assert_eq!(
+            waiver_discount(calling_program, payer, Some(crate::ID)).unwrap_err(),
+            ErrorCode::InvalidFeeWaiver.into()
+        );
+        assert_eq!(
+            waiver_discount(calling_program, payer, Some(Pubkey::new_unique())).unwrap_err(),
+            ErrorCode::InvalidFeeWaiver.into()
+        );
+    }
+
+    #[test]
+    fn discounts_round_the_fee_down() {
+        assert_eq!(apply_discount(101, 2_500).unwrap(), 75);
+        assert_eq!(apply_discount(101, 0).unwrap(), 101);
+        assert_eq!(apply_discount(101, 10_000).unwrap(), 0);
+        assert_eq!(apply_discount(u64::MAX, 0).unwrap(), u64::MAX);
+        assert_eq!(
+            apply_discount(101, 10_001).unwrap_err(),
+            ErrorCode::InvalidFeeWaiver.into()
+        );
+    }
+}
@@ -1,13 +1,98 @@
 use anchor_lang::prelude::*;
 
 use anchor_spl::metadata::mpl_token_metadata::types::{Collection, Creator, DataV2};
-use anchor_spl::metadata::{CreateMetadataAccountsV3, UnverifySizedCollectionItem, VerifySizedCollectionItem, CreateMasterEditionV3, create_metadata_accounts_v3, update_metadata_accounts_v2, unverify_sized_collection_item, verify_sized_collection_item, create_master_edition_v3, UpdateMetadataAccountsV2};
-use anchor_spl::metadata::mpl_token_metadata::accounts::Metadata;
+use anchor_spl::metadata::{BurnNft, CreateMetadataAccountsV3, UnverifySizedCollectionItem, VerifySizedCollectionItem, CreateMasterEditionV3, create_metadata_accounts_v3, update_metadata_accounts_v2, unverify_sized_collection_item, verify_sized_collection_item, create_master_edition_v3, UpdateMetadataAccountsV2};
+use anchor_spl::metadata::mpl_token_metadata::accounts::{CollectionAuthorityRecord, MasterEdition, Metadata};
+use anchor_spl::metadata::mpl_token_metadata::types::Key as MetaplexKey;
+use anchor_spl::metadata::mpl_token_metadata::ID as MPL_TOKEN_METADATA_ID;
+use anchor_spl::metadata::mpl_token_metadata::MAX_URI_LENGTH;
+use crate::error::ErrorCode;
 use crate::seeds::TOKEN_AUTHORITY_SEED;
 use crate::Level;
 
+/// The values substituted into the placeholders of a level's uri template
+pub struct UriParams {
+    pub offset: u64,
+    pub level_index: usize,
+    pub mint: Pubkey,
+    // only required if the template contains the {owner} placeholder
+    pub owner: Option<Pubkey>,
+}
+
+/// The longest value of each placeholder: a u64 offset, a u16 level index
+/// and base58 encoded pubkeys
+const PLACEHOLDER_MAX_LENGTHS: [(&str, usize); 4] =
+    [("{offset}", 20), ("{level}", 5), ("{mint}", 44), ("{owner}", 44)];
+
+/// The length of a level's uri once its placeholders are expanded to their longest values
+pub fn max_expanded_uri_length(template: &str) -> usize {
+    PLACEHOLDER_MAX_LENGTHS
+        .iter()
+        .fold(template.len(), |length, (placeholder, max_length)| {
+            let count = template.matches(placeholder).count();
+            length - count * placeholder.len() + count * max_length
+        })
+}
+
+/// Expands the {offset}, {level}, {mint} and {owner} placeholders of a level's uri.
+/// The expanded uri must still fit within the Metaplex limit
+pub fn expand_uri(template: &str, params: &UriParams) -> Result<String> {
+    let mut uri = template
+        .replace("{offset}", &params.offset.to_string())
+        .replace("{level}", &params.level_index.to_string())
+        .replace("{mint}", &params.mint.to_string());
+    if uri.contains("{owner}") {
+        let owner = params.owner.ok_or(ErrorCode::MissingUriOwner)?;
+        uri = uri.replace("{owner}", &owner.to_string());
+    }
+    require_gte!(MAX_URI_LENGTH, uri.len(), ErrorCode::MetadataUriTooLong);
+    Ok(uri)
+}
+
+/// The creators of an NFT at the given level. The token authority is always a verified creator,
+/// other creators are unverified unless they have already verified the existing metadata
+fn level_creators(
+    level: &Level,
+    token_authority: &Pubkey,
+    existing: Option<&[Creator]>,
+) -> Vec<Creator> {
+    let is_verified = |address: &Pubkey| {
+        address == token_authority
+            || existing.is_some_and(|existing| {
+                existing
+                    .iter()
+                    .any(|creator| creator.address == *address && creator.verified)
+            })
+    };
+
+    let mut creators: Vec<Creator> = level
+        .creators
+        .iter()
+        .map(|creator| Creator {
+            address: creator.address,
+            verified: is_verified(&creator.address),
+            share: creator.share,
+        })
+        .collect();
+    if creators.is_empty() {
+        creators.push(Creator {
+            address: *token_authority,
+            verified: true,
+            share: 100,
+        });
+    } else if !creators.iter().any(|creator| creator.address == *token_authority) {
+        creators.push(Creator {
+            address: *token_authority,
+            verified: true,
+            share: 0,
+        });
+    }
+    creators
+}
+
 pub fn create_metadata_account<'a>(
     level: &Level,
+    uri_params: &UriParams,
     metadata_account: AccountInfo<'a>,
     mint: AccountInfo<'a>,
     payer: AccountInfo<'a>,
@@ -18,11 +103,7 @@ pub fn create_metadata_account<'a>(
     system_program: AccountInfo<'a>,
     rent: AccountInfo<'a>,
 ) -> Result<()> {
-    let creator = vec![Creator {
-        address: token_authority.key(),
-        verified: true,
-        share: 100,
-    }];
+    let creators = level_creators(level, &token_authority.key(), None);
     let collection = Collection {
         verified: false,
         key: level.collection_mint,
@@ -51,9 +132,9 @@ pub fn create_metadata_account<'a>(
         DataV2 {
             name: level.name.clone(),
             symbol: level.symbol.clone(),
-            uri: level.uri.clone(),
-            seller_fee_basis_points: 0,
-            creators: Some(creator),
+            uri: expand_uri(&level.uri, uri_params)?,
+            seller_fee_basis_points: level.royalty_basis_points.unwrap_or(0),
+            creators: Some(creators),
             collection: Some(collection),
             uses: None,
         },
@@ -101,6 +182,33 @@ pub fn unverify_nft<'a>(
     Ok(())
 }
 
+/// Burns the nft and closes its token, metadata and master edition accounts.
+/// The nft must be unverified from its collection first, the owner receives the rent
+pub fn burn_nft<'a>(
+    metadata: AccountInfo<'a>,
+    owner: AccountInfo<'a>,
+    mint: AccountInfo<'a>,
+    token_account: AccountInfo<'a>,
+    master_edition: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    token_metadata_program: AccountInfo<'a>,
+) -> Result<()> {
+    let cpi_ctx = CpiContext::new(
+        token_metadata_program,
+        BurnNft {
+            metadata,
+            owner,
+            mint,
+            token: token_account,
+            edition: master_edition,
+            spl_token: token_program,
+        }
+    );
+    anchor_spl::metadata::burn_nft(cpi_ctx, None)?;
+
+    Ok(())
+}
+
 pub fn verify_nft<'a>(
     unverified_metadata: AccountInfo<'a>,
     payer: AccountInfo<'a>,
@@ -180,29 +288,34 @@ pub fn create_master_edition_account<'a>(
 
 pub fn update_metadata<'a>(
     new_level: &Level,
+    uri_params: &UriParams,
     metadata: AccountInfo<'a>,
     global_state: &Pubkey,
     token_authority: AccountInfo<'a>,
     token_authority_bump: u8,
     token_metadata_program: AccountInfo<'a>,
 ) -> Result<()> {
-    let creator = vec![Creator {
-        address: token_authority.key(),
-        verified: true,
-        share: 100,
-    }];
+    let existing = Metadata::try_from(&metadata)?;
+    let creators = level_creators(
+        new_level,
+        &token_authority.key(),
+        existing.creators.as_deref(),
+    );
 
+    // the verification is kept if the collection is unchanged, e.g. when refreshing metadata
     let new_collection = Collection {
-        verified: false,
+        verified: existing.collection.as_ref().is_some_and(|collection| {
+            collection.verified && collection.key == new_level.collection_mint
+        }),
         key: new_level.collection_mint,
     };
 
     let new_data = DataV2 {
         name: new_level.name.clone(),
         symbol: new_level.symbol.clone(),
-        uri: new_level.uri.clone(),
-        seller_fee_basis_points: 0,
-        creators: Some(creator),
+        uri: expand_uri(&new_level.uri, uri_params)?,
+        seller_fee_basis_points: new_level.royalty_basis_points.unwrap_or(0),
+        creators: Some(creators),
         collection: Some(new_collection),
         uses: None,
     };
@@ -234,9 +347,150 @@ pub fn update_metadata<'a>(
 /// Helper function that affirms that the metadata originates
 /// from this program instance. It does this by comparison with
 /// the mint which itself is validated by its unique offset_metadata
-pub fn check_metadata_account<'a>(metadata: &AccountInfo<'a>, mint: &AccountInfo<'a>) -> bool {
-    let state: Metadata =
-        Metadata::try_from(&metadata.to_account_info()).unwrap();
+pub fn check_metadata_account<'a>(metadata: &AccountInfo<'a>, mint: &AccountInfo<'a>) -> Result<()> {
+    require_keys_eq!(*metadata.owner, MPL_TOKEN_METADATA_ID, ErrorCode::InvalidMetadata);
+    let state = Metadata::try_from(metadata).map_err(|_| ErrorCode::InvalidMetadata)?;
+    require_keys_eq!(mint.key(), state.mint, ErrorCode::InvalidMetadata);
+    Ok(())
+}
+
+/// Checks that a collection mint can hold the NFTs of a level:
+/// it must have metadata and a master edition, be a sized collection,
+/// and the token authority must be able to verify items in it, either as its update authority
+/// or through an approved collection authority record.
+/// Expects the collection metadata, master edition and authority record accounts, in that order.
+/// The authority record is only read if the token authority is not the update authority.
+pub fn check_collection(
+    collection_mint: &Pubkey,
+    token_authority: &Pubkey,
+    accounts: &[AccountInfo],
+) -> Result<()> {
+    let [metadata, master_edition, authority_record] = accounts else {
+        return Err(ErrorCode::MissingCollectionAccounts.into());
+    };
+
+    require_keys_eq!(
+        metadata.key(),
+        Metadata::find_pda(collection_mint).0,
+        ErrorCode::InvalidCollection
+    );
+    require_keys_eq!(
+        *metadata.owner,
+        MPL_TOKEN_METADATA_ID,
+        ErrorCode::InvalidCollection
+    );
+    let metadata_state =
+        Metadata::try_from(metadata).map_err(|_| ErrorCode::InvalidCollection)?;
+    require!(
+        metadata_state.key == MetaplexKey::MetadataV1 && metadata_state.collection_details.is_some(),
+        ErrorCode::InvalidCollection
+    );
+
+    require_keys_eq!(
+        master_edition.key(),
+        MasterEdition::find_pda(collection_mint).0,
+        ErrorCode::InvalidCollection
+    );
+    require_keys_eq!(
+        *master_edition.owner,
+        MPL_TOKEN_METADATA_ID,
+        ErrorCode::InvalidCollection
+    );
+    let master_edition_state =
+        MasterEdition::try_from(master_edition).map_err(|_| ErrorCode::InvalidCollection)?;
+    require!(
+        master_edition_state.key == MetaplexKey::MasterEditionV2,
+        ErrorCode::InvalidCollection
+    );
+
+    if metadata_state.update_authority == *token_authority {
+        return Ok(());
+    }
+
+    require_keys_eq!(
+        authority_record.key(),
+        CollectionAuthorityRecord::find_pda(collection_mint, token_authority).0,
+        ErrorCode::InvalidCollectionAuthority
+    );
+    require_keys_eq!(
+        *authority_record.owner,
+        MPL_TOKEN_METADATA_ID,
+        ErrorCode::InvalidCollectionAuthority
+    );
+    let authority_record_state = CollectionAuthorityRecord::try_from(authority_record)
+        .map_err(|_| ErrorCode::InvalidCollectionAuthority)?;
+    // records created before update authorities were tracked apply to any update authority
+    let approved_by = authority_record_state
+        .update_authority
+        .unwrap_or(metadata_state.update_authority);
+    require!(
+        authority_record_state.key == MetaplexKey::CollectionAuthorityRecord
+            && approved_by == metadata_state.update_authority,
+        ErrorCode::InvalidCollectionAuthority
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_expanded_uri_length_covers_the_longest_values() {
+        let template = "https://sunrise/{level}/{offset}/{mint}-{owner}.json?{level}";
+        let params = UriParams {
+            offset: u64::MAX,
+            level_index: u16::MAX as usize,
+            mint: Pubkey::new_from_array([255; 32]),
+            owner: Some(Pubkey::new_from_array([255; 32])),
+        };
+        let expanded = expand_uri(template, &params).unwrap();
+        assert_eq!(expanded.len(), max_expanded_uri_length(template));
+    }
+
+    fn check_metadata_account_with(owner: Pubkey, mut data: Vec<u8>) -> Result<()> {
+        let (metadata_key, mint_key, mint_owner) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut metadata_lamports, mut mint_lamports) = (0, 0);
+        let metadata = AccountInfo::new(
+            &metadata_key,
+            false,
+            false,
+            &mut metadata_lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let mint = AccountInfo::new(
+            &mint_key,
+            false,
+            false,
+            &mut mint_lamports,
+            &mut [],
+            &mint_owner,
+            false,
+            0,
+        );
+        check_metadata_account(&metadata, &mint)
+    }
+
+    #[test]
+    fn check_metadata_account_rejects_other_accounts_without_panicking() {
+        assert_eq!(
+            check_metadata_account_with(Pubkey::new_unique(), vec![0; 8]),
+            Err(ErrorCode::InvalidMetadata.into())
+        );
+        assert_eq!(
+            check_metadata_account_with(MPL_TOKEN_METADATA_ID, vec![1, 2, 3]),
+            Err(ErrorCode::InvalidMetadata.into())
+        );
+    }
 
-    mint.key() == state.mint
+    #[test]
+    fn max_expanded_uri_length_without_placeholders_is_the_template_length() {
+        let template = "https://sunrise/level.json";
+        assert_eq!(max_expanded_uri_length(template), template.len());
+    }
 }
@@ -1,3 +1,4 @@
 pub(crate) mod fee;
 pub(crate) mod metaplex;
+pub(crate) mod tiers;
 pub(crate) mod token;
@@ -0,0 +1,317 @@
+use crate::error::ErrorCode;
+use crate::seeds::{offset_tiers_version_seed, OFFSET_TIERS_SEED};
+use crate::state::{FeeDistribution, Level, LevelCreator, OffsetTiers};
+use crate::utils::fee::validate_fee_config;
+use crate::utils::metaplex::{check_collection, max_expanded_uri_length};
+use anchor_lang::prelude::*;
+use anchor_spl::metadata::mpl_token_metadata::{
+    MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH,
+};
+
+/// Checks the invariants of a complete set of offset tiers:
+/// the first level starts at offset 0 with no minimum principal, offsets are strictly increasing,
+/// and the metadata of each level fits within the Metaplex limits,
+/// with the placeholders of its uri expanded to their longest values.
+/// Like the global fees, level fees require a fee distribution so that they can be withdrawn.
+pub fn validate_levels(levels: &[Level], fee_distribution: &Option<FeeDistribution>) -> Result<()> {
+    let base = levels.first().ok_or(ErrorCode::NoOffsetTiers)?;
+    require_eq!(base.offset, 0, ErrorCode::InvalidBaseOffset);
+    // NFTs that do not meet any level's thresholds default to the first level
+    require_eq!(base.min_principal, 0, ErrorCode::InvalidBaseMinPrincipal);
+
+    for pair in levels.windows(2) {
+        require_gt!(
+            pair[1].offset,
+            pair[0].offset,
+            ErrorCode::UnorderedOffsetTiers
+        );
+    }
+
+    for level in levels {
+        validate_level(level, fee_distribution)?;
+    }
+    Ok(())
+}
+
+/// Whether any level of any version of the offset tiers overrides the fee.
+/// Expects the offset tiers of every version up to the latest, in order.
+/// The first version may not have been created yet
+pub fn levels_charge_fees(
+    global_state: &Pubkey,
+    tiers_version: u16,
+    offset_tiers_accounts: &[AccountInfo],
+) -> Result<bool> {
+    require_eq!(
+        offset_tiers_accounts.len(),
+        tiers_version as usize + 1,
+        ErrorCode::NoOffsetTiers
+    );
+    for (version, account) in (0..=tiers_version).zip(offset_tiers_accounts) {
+        let (expected_address, _) = Pubkey::find_program_address(
+            &[
+                OFFSET_TIERS_SEED,
+                global_state.as_ref(),
+                offset_tiers_version_seed(version).as_ref(),
+            ],
+            &crate::ID,
+        );
+        require_keys_eq!(account.key(), expected_address, ErrorCode::NoOffsetTiers);
+        if version == 0 && account.data_is_empty() {
+            continue;
+        }
+        require_keys_eq!(*account.owner, crate::ID, ErrorCode::NoOffsetTiers);
+        let offset_tiers = OffsetTiers::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+        if offset_tiers.levels.iter().any(|level| level.fee.is_some()) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The number of accounts expected per collection in `verify_collections`:
+/// the collection metadata, master edition and the token authority's collection authority record
+pub const COLLECTION_ACCOUNTS: usize = 3;
+
+/// Checks the collection mint of each level that is not already used by one of the known levels.
+/// The accounts of each newly registered collection are expected in the remaining accounts,
+/// in groups of `COLLECTION_ACCOUNTS`, in the order of the levels.
+pub fn verify_collections(
+    levels: &[Level],
+    known_levels: &[Level],
+    token_authority: &Pubkey,
+    remaining_accounts: &[AccountInfo],
+) -> Result<()> {
+    let mut collection_accounts = remaining_accounts.chunks(COLLECTION_ACCOUNTS);
+    for level in levels {
+        if known_levels
+            .iter()
+            .any(|known| known.collection_mint == level.collection_mint)
+        {
+            continue;
+        }
+        let accounts = collection_accounts
+            .next()
+            .ok_or(ErrorCode::MissingCollectionAccounts)?;
+        check_collection(&level.collection_mint, token_authority, accounts)?;
+    }
+    Ok(())
+}
+
+fn validate_level(level: &Level, fee_distribution: &Option<FeeDistribution>) -> Result<()> {
+    require_gte!(MAX_URI_LENGTH, level.uri.len(), ErrorCode::LevelUriTooLong);
+    require_gte!(
+        MAX_URI_LENGTH,
+        max_expanded_uri_length(&level.uri),
+        ErrorCode::MetadataUriTooLong
+    );
+    require_gte!(
+        MAX_NAME_LENGTH,
+        level.name.len(),
+        ErrorCode::LevelNameTooLong
+    );
+    require_gte!(
+        MAX_SYMBOL_LENGTH,
+        level.symbol.len(),
+        ErrorCode::LevelSymbolTooLong
+    );
+    if let Some(fee_config) = &level.fee {
+        validate_fee_config(fee_config)?;
+        require!(fee_distribution.is_some(), ErrorCode::NoFeeDistribution);
+    }
+    validate_royalties(level)
+}
+
+fn validate_royalties(level: &Level) -> Result<()> {
+    if let Some(royalty_basis_points) = level.royalty_basis_points {
+        require_gte!(10_000, royalty_basis_points, ErrorCode::InvalidRoyalty);
+    }
+
+    if level.creators.is_empty() {
+        return Ok(());
+    }
+    require_gte!(
+        LevelCreator::MAX_CREATORS,
+        level.creators.len(),
+        ErrorCode::TooManyCreators
+    );
+    for (index, creator) in level.creators.iter().enumerate() {
+        require!(
+            level.creators[..index]
+                .iter()
+                .all(|other| other.address != creator.address),
+            ErrorCode::DuplicateCreator
+        );
+    }
+    let total_share: u16 = level
+        .creators
+        .iter()
+        .map(|creator| creator.share as u16)
+        .sum();
+    require_eq!(total_share, 100, ErrorCode::InvalidCreatorShares);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(offset: u64) -> Level {
+        Level {
+            offset,
+            min_principal: 0,
+            min_duration_at_previous_level: 0,
+            uri: "https://example.com/{level}.json".to_string(),
+            name: "Level".to_string(),
+            symbol: "LVL".to_string(),
+            collection_mint: Pubkey::new_unique(),
+            fee: None,
+            royalty_basis_points: None,
+            creators: vec![],
+        }
+    }
+
+    fn creators(shares: &[u8]) -> Vec<LevelCreator> {
+        shares
+            .iter()
+            .map(|&share| LevelCreator {
+                address: Pubkey::new_unique(),
+                share,
+            })
+            .collect()
+    }
+
+    fn validate_single(level: Level) -> Result<()> {
+        validate_levels(&[level], &None)
+    }
+
+    #[test]
+    fn accepts_ordered_levels_from_zero() {
+        assert!(validate_levels(&[level(0), level(100), level(200)], &None).is_ok());
+    }
+
+    #[test]
+    fn the_base_level_has_no_thresholds() {
+        assert_eq!(
+            validate_levels(&[level(1), level(100)], &None).unwrap_err(),
+            ErrorCode::InvalidBaseOffset.into()
+        );
+        let base = Level {
+            min_principal: 1,
+            ..level(0)
+        };
+        assert_eq!(
+            validate_levels(&[base, level(100)], &None).unwrap_err(),
+            ErrorCode::InvalidBaseMinPrincipal.into()
+        );
+    }
+
+    #[test]
+    fn offsets_must_strictly_increase() {
+        assert_eq!(
+            validate_levels(&[level(0), level(200), level(100)], &None).unwrap_err(),
+            ErrorCode::UnorderedOffsetTiers.into()
+        );
+        assert_eq!(
+            validate_levels(&[level(0), level(100), level(100)], &None).unwrap_err(),
+            ErrorCode::UnorderedOffsetTiers.into()
+        );
+    }
+
+    #[test]
+    fn metadata_must_fit_within_the_metaplex_limits() {
+        let name = Level {
+            name: "n".repeat(MAX_NAME_LENGTH + 1),
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(name).unwrap_err(),
+            ErrorCode::LevelNameTooLong.into()
+        );
+        let symbol = Level {
+            symbol: "s".repeat(MAX_SYMBOL_LENGTH + 1),
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(symbol).unwrap_err(),
+            ErrorCode::LevelSymbolTooLong.into()
+        );
+        let uri = Level {
+            uri: "u".repeat(MAX_URI_LENGTH + 1),
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(uri).unwrap_err(),
+            ErrorCode::LevelUriTooLong.into()
+        );
+    }
+
+    #[test]
+    fn uris_must_fit_with_their_placeholders_expanded() {
+        // fits as a template, but not once {owner} is replaced by a pubkey
+        let template = format!("{}{{owner}}", "u".repeat(MAX_URI_LENGTH - 10));
+        assert!(template.len() <= MAX_URI_LENGTH);
+        let uri = Level {
+            uri: template,
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(uri).unwrap_err(),
+            ErrorCode::MetadataUriTooLong.into()
+        );
+    }
+
+    #[test]
+    fn royalties_are_at_most_100_percent() {
+        let full = Level {
+            royalty_basis_points: Some(10_000),
+            ..level(0)
+        };
+        assert!(validate_single(full).is_ok());
+        let over = Level {
+            royalty_basis_points: Some(10_001),
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(over).unwrap_err(),
+            ErrorCode::InvalidRoyalty.into()
+        );
+    }
+
+    #[test]
+    fn creators_are_unique_and_share_100_percent() {
+        let valid = Level {
+            creators: creators(&[25, 25, 25, 25]),
+            ..level(0)
+        };
+        assert!(validate_single(valid).is_ok());
+
+        let too_many = Level {
+            creators: creators(&[20, 20, 20, 20, 20]),
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(too_many).unwrap_err(),
+            ErrorCode::TooManyCreators.into()
+        );
+
+        let mut duplicated = creators(&[50, 50]);
+        duplicated[1].address = duplicated[0].address;
+        let duplicate = Level {
+            creators: duplicated,
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(duplicate).unwrap_err(),
+            ErrorCode::DuplicateCreator.into()
+        );
+
+        let short = Level {
+            creators: creators(&[50, 49]),
+            ..level(0)
+        };
+        assert_eq!(
+            validate_single(short).unwrap_err(),
+            ErrorCode::InvalidCreatorShares.into()
+        );
+    }
+}
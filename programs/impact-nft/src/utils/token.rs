@@ -4,6 +4,7 @@ use anchor_lang::solana_program::program::invoke;
 use anchor_lang::solana_program::system_instruction::create_account;
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{self, Token};
+use anchor_spl::token_interface::TokenInterface;
 
 pub fn create_mint<'a>(
     payer: &AccountInfo<'a>,
@@ -92,3 +93,28 @@ pub fn create_token_account<'a>(
 
     Ok(())
 }
+
+pub fn create_fee_vault_token_account<'a>(
+    associated_token_program: &Program<'a, AssociatedToken>,
+    payer: &AccountInfo<'a>,
+    token_account: &AccountInfo<'a>,
+    fee_vault: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    system_program: &Program<'a, System>,
+    token_program: &Interface<'a, TokenInterface>,
+) -> Result<()> {
+    msg!("Creating fee vault token account");
+    anchor_spl::associated_token::create(CpiContext::new(
+        associated_token_program.to_account_info(),
+        anchor_spl::associated_token::Create {
+            payer: payer.to_account_info(),
+            associated_token: token_account.to_account_info(),
+            authority: fee_vault.to_account_info(),
+            mint: mint.to_account_info(),
+            system_program: system_program.to_account_info(),
+            token_program: token_program.to_account_info(),
+        },
+    ))?;
+
+    Ok(())
+}